@@ -18,8 +18,10 @@ fn main() {
 
 pub fn write(file: &mut File) {
     // Construct a writer for writing &str
-    // This won't perform any IO operations
-    let mut writer: SimpleJournalWriter<&str> = SimpleJournalWriter::new(file);
+    // This will check the file header if the file is not empty,
+    // new files get their header with the first entry
+    let mut writer: SimpleJournalWriter<&str> = SimpleJournalWriter::new(file)
+        .unwrap();
 
     // Write a single entry
     writer.store_entry("Hello World!").unwrap();
@@ -31,11 +33,13 @@ pub fn write(file: &mut File) {
 
 pub fn read(file: &mut File) {
     // Construct a reader for reading Strings
-    // This won't perform any IO operations
+    // This will fail if the file is not a journal written with the same serializer
     // Note: We cannot use &str here, since the deserialized value
-    //       musst be owned
+    //       musst be owned. Use SimpleJournalSlice to borrow entries
+    //       from a journal that is already in memory.
     let mut reader: SimpleJournalReader<String> =
-        SimpleJournalReader::new(file);
+        SimpleJournalReader::new(file)
+        .unwrap();
 
     // You can use this reader in a simple for loop
     for value in &mut reader {
@@ -20,14 +20,14 @@ pub fn write(file: &mut File) {
     // Create journal file and store some entries,
     // see examples/01_simple.rs
     let mut writer: SimpleJournalWriter<String> =
-        SimpleJournalWriter::new(file);
+        SimpleJournalWriter::new(file)
+        .unwrap();
 
     writer.store_entry("Hello World!".into()).unwrap();
     writer.store_entry("Another Entry".into()).unwrap();
     writer.store_entry("Even more data".into()).unwrap();
     writer.store_entries(
             (4..100)
-            .into_iter()
             .map(|x|
                 format!("This is entry no {}", x)))
         .unwrap();
@@ -0,0 +1,181 @@
+use std::io::{Read, Write};
+use std::fmt::{self, Debug, Display};
+
+use crate::*;
+use crate::header::Layer;
+
+/// Compresses the serialized entries of a journal, see [`Compressed`](Compressed).
+///
+/// Implementations for zstd and lz4 are available with the `zstd` and `lz4` features.
+pub trait Codec : Copy + Debug + 'static {
+    /// Identifies the codec in the file header. Readers refuse to open a
+    /// journal that was compressed with another codec.
+    const ID: &'static str;
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>>;
+}
+
+/// Wraps a serializer or deserializer and compresses every entry with the codec `C`.
+///
+/// Every entry is compressed on its own, so the offsets in the index of an
+/// [`IndexedJournal`](crate::indexed_journal::IndexedJournal) still point
+/// to single entries and random access keeps working. The flip side is that
+/// the codec can't exploit redundancy between entries: many small entries
+/// barely shrink, while entries that batch related values, e.g. in a `Vec`,
+/// compress well.
+///
+/// ```ignore
+/// let journal = IndexedJournal::with_serializer(file,
+///     Compressed::new(BincodeSerializer, Zstd::default()),
+///     Compressed::new(BincodeDeserializer, Zstd::default()))?;
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Compressed<S, C> {
+    inner: S,
+    codec: C,
+}
+
+impl<S, C> Compressed<S, C> {
+    pub fn new(inner: S, codec: C) -> Self {
+        Self { inner, codec }
+    }
+}
+
+/// The error of a [`Compressed`](Compressed) serializer or deserializer.
+#[derive(Debug)]
+pub enum CompressionError<E> {
+    /// The wrapped serializer or deserializer failed.
+    Serialization(E),
+    /// The codec failed, e.g. because the compressed data is invalid.
+    Codec(std::io::Error),
+}
+
+impl<E: Display> Display for CompressionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionError::Serialization(err) => write!(f, "{}", err),
+            CompressionError::Codec(err) => write!(f, "compression failed: {}", err),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for CompressionError<E> {}
+
+impl<T, S, C> JournalSerialize<T> for Compressed<S, C>
+where S: JournalSerialize<T>, C: Codec {
+    type Error = CompressionError<S::Error>;
+    const FORMAT_ID: &'static str = S::FORMAT_ID;
+    const CODEC_ID: Option<&'static str> = Some(C::ID);
+    const CIPHER_ID: Option<&'static str> = S::CIPHER_ID;
+    const VERSIONED: bool = S::VERSIONED;
+    const LAYERS: Layers = S::LAYERS.wrap(Layer::Codec);
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        self.serialize_at(value, EntryPosition::default(), writer)
+    }
+
+    fn serialize_at(&self, value: T, position: EntryPosition, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        let mut serialized = Vec::new();
+        self.inner.serialize_at(value, position, &mut serialized)
+            .map_err(CompressionError::Serialization)?;
+        self.codec.compress(&serialized)
+            .and_then(|compressed| writer.write_all(&compressed))
+            .map_err(CompressionError::Codec)
+    }
+}
+
+impl<T, D, C> JournalDeserialize<T> for Compressed<D, C>
+where D: JournalDeserialize<T>, C: Codec {
+    type Error = CompressionError<D::Error>;
+    const FORMAT_ID: &'static str = D::FORMAT_ID;
+    const CODEC_ID: Option<&'static str> = Some(C::ID);
+    const CIPHER_ID: Option<&'static str> = D::CIPHER_ID;
+    const VERSIONED: bool = D::VERSIONED;
+    const LAYERS: Layers = D::LAYERS.wrap(Layer::Codec);
+    const DEPENDS_ON_POSITION: bool = D::DEPENDS_ON_POSITION;
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        self.deserialize_at(EntryPosition::default(), reader)
+    }
+
+    fn deserialize_at(&self, position: EntryPosition, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        let mut compressed = Vec::new();
+        reader.read_to_end(&mut compressed)
+            .map_err(CompressionError::Codec)?;
+        let serialized = self.codec.decompress(&compressed)
+            .map_err(CompressionError::Codec)?;
+        self.inner.deserialize_at(position, &mut serialized.as_slice())
+            .map_err(CompressionError::Serialization)
+    }
+
+    fn relocate(&self, entry: Vec<u8>, from: EntryPosition, to: EntryPosition) -> Result<Vec<u8>, Self::Error> {
+        // don't compress everything again if the inner entry stays the same
+        if !D::DEPENDS_ON_POSITION {
+            return Ok(entry);
+        }
+        let serialized = self.codec.decompress(&entry)
+            .map_err(CompressionError::Codec)?;
+        let serialized = self.inner.relocate(serialized, from, to)
+            .map_err(CompressionError::Serialization)?;
+        self.codec.compress(&serialized)
+            .map_err(CompressionError::Codec)
+    }
+
+    fn without_versions(self) -> Option<Self> {
+        let Self { inner, codec } = self;
+        inner.without_versions()
+            .map(|inner| Self { inner, codec })
+    }
+}
+
+/// The zstd codec, requires the `zstd` feature.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Zstd {
+    /// The compression level, 0 selects zstd's default
+    pub level: i32,
+}
+
+#[cfg(feature = "zstd")]
+impl Codec for Zstd {
+    const ID: &'static str = "zstd";
+
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::bulk::compress(data, self.level)
+    }
+
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        zstd::stream::decode_all(data)
+    }
+}
+
+/// lz4 can't expand data by more than this factor, a larger size prefix means
+/// the entry is corrupted and must not be allocated.
+#[cfg(feature = "lz4")]
+const LZ4_MAX_RATIO: usize = 255;
+
+/// The lz4 codec, requires the `lz4` feature.
+#[cfg(feature = "lz4")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl Codec for Lz4 {
+    const ID: &'static str = "lz4";
+
+    fn compress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        Ok(lz4_flex::compress_prepend_size(data))
+    }
+
+    fn decompress(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        use std::convert::TryFrom;
+        let size = data.get(..4)
+            .map(|prefix| u32::from_le_bytes(<[u8; 4]>::try_from(prefix).unwrap()) as usize)
+            .unwrap_or(0);
+        if size > (data.len() + 1).saturating_mul(LZ4_MAX_RATIO) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "lz4 size prefix exceeds the maximum ratio"));
+        }
+        lz4_flex::decompress_size_prepended(data)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
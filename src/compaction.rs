@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter, ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::*;
+use crate::header::FileHeader;
+
+/// What the key extractor of [`compact`](compact) found in an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionKey<K> {
+    /// The entry holds the latest value for the key, until a newer entry with the same key follows.
+    Value(K),
+    /// The key was deleted. Tombstones are dropped by the compaction together with all older values.
+    Tombstone(K),
+}
+
+impl<K> CompactionKey<K> {
+    fn into_key(self) -> K {
+        match self {
+            CompactionKey::Value(key) | CompactionKey::Tombstone(key) => key,
+        }
+    }
+}
+
+/// Rewrite the journal at `path`, keeping only the newest entry for every key.
+///
+/// Only the entries before the index `up_to` are compacted, the ones after it
+/// are kept as they are. Pass `None` to compact the whole journal. An entry
+/// is dropped if a newer entry with the same key exists anywhere in the
+/// journal or if it is a tombstone. The surviving entries keep their order,
+/// but their indices change.
+///
+/// The compacted journal is written to a temporary file next to `path`,
+/// which replaces the journal only once it was synced. A crash during
+/// compaction leaves the original journal untouched. The journal must not be
+/// open while it is compacted.
+///
+/// Returns the number of dropped entries.
+pub fn compact<T, K, P, X>(path: P, up_to: Option<usize>, key: X) -> Result<usize, JournalError<bincode::Error>>
+where T: for<'de> serde::Deserialize<'de>,
+      K: Hash + Eq,
+      P: AsRef<Path>,
+      X: FnMut(&T) -> CompactionKey<K> {
+    compact_with_deserializer(path, BincodeDeserializer, up_to, key)
+}
+
+/// Like [`compact`](compact), but you can provide your own deserializer.
+pub fn compact_with_deserializer<T, D, K, P, X>(path: P, deserializer: D, up_to: Option<usize>, key: X) -> Result<usize, JournalError<D::Error>>
+where D: JournalDeserialize<T>,
+      K: Hash + Eq,
+      P: AsRef<Path>,
+      X: FnMut(&T) -> CompactionKey<K> {
+    compact_journal(path.as_ref(), None, deserializer, up_to, key)
+}
+
+/// Like [`compact_with_deserializer`](compact_with_deserializer), but also
+/// deletes the sidecar index at `index_path` that was kept with
+/// [`IndexedJournal::with_index_file`](IndexedJournal::with_index_file).
+///
+/// Its offsets would point into the old journal, the index is rebuilt the
+/// next time the journal is opened with it.
+pub fn compact_with_index_file<T, D, K, P, Q, X>(path: P, index_path: Q, deserializer: D, up_to: Option<usize>, key: X) -> Result<usize, JournalError<D::Error>>
+where D: JournalDeserialize<T>,
+      K: Hash + Eq,
+      P: AsRef<Path>,
+      Q: AsRef<Path>,
+      X: FnMut(&T) -> CompactionKey<K> {
+    compact_journal(path.as_ref(), Some(index_path.as_ref()), deserializer, up_to, key)
+}
+
+fn compact_journal<T, D, K, X>(path: &Path, index_path: Option<&Path>, deserializer: D, up_to: Option<usize>, mut key: X) -> Result<usize, JournalError<D::Error>>
+where D: JournalDeserialize<T>,
+      K: Hash + Eq,
+      X: FnMut(&T) -> CompactionKey<K> {
+    let mut file = File::open(path)
+        .map_err(JournalError::IOError)?;
+    let header = match FileHeader::read(&mut file)? {
+        Some(header) => header,
+        None => return Ok(0),
+    };
+    let deserializer = header.verify_deserializer(deserializer)?;
+    let up_to = up_to.unwrap_or(usize::MAX);
+
+    // the first pass finds the newest entry for every key
+    let mut newest = HashMap::new();
+    for_each_entry(&mut file, &header, |index, offset, payload| {
+        let value = decode_entry(&deserializer, header.position(offset), &payload)?;
+        newest.insert(key(&value).into_key(), index);
+        Ok(())
+    })?;
+
+    let temp_path = compaction_path(path);
+    let temp_file = OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path)
+        .map_err(JournalError::IOError)?;
+    let mut writer = BufWriter::new(temp_file);
+    // a new id makes sure that a stale sidecar index is never trusted for the compacted journal
+    let compacted = header.clone().with_new_journal_id();
+    writer.write_all(&compacted.encode())
+        .map_err(JournalError::IOError)?;
+
+    let mut dropped = 0;
+    let mut frame = Vec::new();
+    let mut end = compacted.size();
+    for_each_entry(&mut file, &header, |index, offset, payload| {
+        if index < up_to {
+            let value = decode_entry(&deserializer, header.position(offset), &payload)?;
+            let entry_key = key(&value);
+            let is_tombstone = matches!(entry_key, CompactionKey::Tombstone(_));
+            if is_tombstone || newest.get(&entry_key.into_key()) != Some(&index) {
+                dropped += 1;
+                return Ok(());
+            }
+        }
+        // the payload is copied, there is no need to deserialize and serialize it again,
+        // but the compacted journal has a new id, so it is always at another position
+        let payload = deserializer.relocate(payload, header.position(offset), compacted.position(end))
+            .map_err(JournalError::SerializationError)?;
+        frame.clear();
+        let start = frame::begin_frame(&mut frame);
+        frame.extend_from_slice(&payload);
+        frame::finish_frame(&mut frame, start, header.checksum)
+            .and_then(|()| writer.write_all(&frame))
+            .map_err(JournalError::IOError)?;
+        end += frame.len() as u64;
+        Ok(())
+    })?;
+
+    let temp_file = writer.into_inner()
+        .map_err(|err| JournalError::IOError(err.into_error()))?;
+    temp_file.sync_all()
+        .map_err(JournalError::IOError)?;
+    drop(file);
+    if let Some(index_path) = index_path {
+        match std::fs::remove_file(index_path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(JournalError::IOError(err)),
+            _ => storage::sync_directory(index_path)
+                .map_err(JournalError::IOError)?,
+        }
+    }
+    std::fs::rename(&temp_path, path)
+        .map_err(JournalError::IOError)?;
+    storage::sync_directory(path)
+        .map_err(JournalError::IOError)?;
+    Ok(dropped)
+}
+
+/// Call `f` with the index, offset and payload of every committed entry.
+fn for_each_entry<E, F>(file: &mut File, header: &FileHeader, mut f: F) -> Result<(), JournalError<E>>
+where F: FnMut(usize, u64, Vec<u8>) -> Result<(), JournalError<E>> {
+    let start = header.size();
+    file.seek(SeekFrom::Start(start))
+        .map_err(JournalError::IOError)?;
+    let mut reader = CountingIO::new(BufReader::new(&mut *file)).with_offset(start);
+    let mut entries = frame::EntryReader::default();
+    let mut index = 0;
+    loop {
+        match entries.next(&mut reader, header.checksum) {
+            Ok(Some((offset, payload))) => f(index, offset, payload)?,
+            Ok(None) => return Ok(()),
+            Err((err, offset)) => return Err(err.at(offset)),
+        }
+        index += 1;
+    }
+}
+
+/// The temporary file a compacted journal is written to, e.g. `journal.compact` for `journal`.
+fn compaction_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".compact");
+    path.with_file_name(file_name)
+}
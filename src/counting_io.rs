@@ -13,12 +13,12 @@ impl<IO> CountingIO<IO> {
         }
     }
 
+    #[allow(dead_code)]
     pub fn without_offset(mut self) -> Self {
         self.counter = Some(0);
         self
     }
 
-    #[allow(dead_code)]
     pub fn with_offset(mut self, offset: u64) -> Self {
         self.counter = Some(offset);
         self
@@ -78,7 +78,7 @@ where IO: Seek {
         let result = self.inner.seek(pos);
 
         if let Ok(count) = result {
-            self.counter = Some(count as u64);
+            self.counter = Some(count);
         }
 
         result
@@ -0,0 +1,73 @@
+use std::io::Result;
+use std::time::{Duration, Instant};
+
+use crate::Storage;
+
+/// When written entries are flushed to stable storage.
+///
+/// Without syncing, an entry that was stored successfully can still be lost
+/// on a power failure or kernel crash. Syncing makes writes much slower, so
+/// the policy allows trading durability for throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Leave it to the operating system, the default.
+    #[default]
+    Never,
+    /// Sync after every `store_entry` or `store_entries` call.
+    EveryEntry,
+    /// Sync as soon as this many entries were written since the last sync.
+    Entries(usize),
+    /// Sync as soon as this many bytes were written since the last sync.
+    Bytes(u64),
+    /// Sync on the first write after this much time has passed since the last sync.
+    ///
+    /// There is no background thread, nothing is synced while the journal is idle.
+    Interval(Duration),
+}
+
+/// Keeps track of the writes since the last sync.
+#[derive(Debug)]
+pub(crate) struct SyncTracker {
+    pub(crate) durability: Durability,
+    entries: usize,
+    bytes: u64,
+    last_sync: Instant,
+}
+
+impl SyncTracker {
+    pub(crate) fn new(durability: Durability) -> Self {
+        Self {
+            durability,
+            entries: 0,
+            bytes: 0,
+            last_sync: Instant::now(),
+        }
+    }
+
+    /// Record a write and sync `file` if the policy asks for it.
+    pub(crate) fn written<F: Storage + ?Sized>(&mut self, file: &mut F, entries: usize, bytes: u64) -> Result<()> {
+        self.entries += entries;
+        self.bytes += bytes;
+        let due = match self.durability {
+            Durability::Never => false,
+            Durability::EveryEntry => true,
+            Durability::Entries(count) => self.entries >= count,
+            Durability::Bytes(count) => self.bytes >= count,
+            Durability::Interval(interval) => self.last_sync.elapsed() >= interval,
+        };
+        if due {
+            self.sync(file)?;
+        }
+        Ok(())
+    }
+
+    /// Flush `file` and wait until everything written so far reached stable storage.
+    pub(crate) fn sync<F: Storage + ?Sized>(&mut self, file: &mut F) -> Result<()> {
+        file.flush()?;
+        file.sync()?;
+        self.entries = 0;
+        self.bytes = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::{Read, Write, ErrorKind};
+use std::fmt::{self, Debug, Display};
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use zeroize::Zeroizing;
+
+use crate::*;
+use crate::header::Layer;
+
+/// Every encrypted entry starts with the random nonce it was encrypted with.
+const NONCE_SIZE: usize = 24;
+
+/// Wraps a serializer or deserializer and encrypts every entry with XChaCha20-Poly1305.
+///
+/// Each entry is encrypted on its own with a random nonce, which is stored in
+/// front of it. The authentication tag covers the [`EntryPosition`](crate::EntryPosition)
+/// of the entry as well, that is the random id of the journal and the offset
+/// in it. So a modified entry, a wrong key or an entry that was moved to
+/// another place in the journal or to another journal or segment is detected,
+/// reading fails with [`EncryptionError::Decryption`](EncryptionError::Decryption)
+/// then. This includes reordered entries and entries behind a removed one,
+/// but not entries that were cut off at the end of the journal.
+///
+/// The key is overwritten with zeros when the serializer is dropped, it isn't
+/// `Copy` so every copy of it is a visible `clone`.
+///
+/// Combine it with [`Compressed`](crate::codec::Compressed) by compressing
+/// first, encrypted data doesn't compress.
+///
+/// ```ignore
+/// let journal = IndexedJournal::with_serializer(file,
+///     Encrypted::new(BincodeSerializer, key),
+///     Encrypted::new(BincodeDeserializer, key))?;
+/// ```
+#[derive(Clone)]
+pub struct Encrypted<S> {
+    inner: S,
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl<S> Encrypted<S> {
+    /// Encrypt with the 256 bit `key`, which has to be kept secret.
+    pub fn new(inner: S, key: [u8; 32]) -> Self {
+        Self { inner, key: Zeroizing::new(key) }
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(self.key.as_slice()))
+    }
+
+    /// Encrypt the `plaintext` of the entry at `position` and prepend the nonce.
+    fn encrypt<E>(&self, plaintext: &[u8], position: EntryPosition) -> Result<Vec<u8>, EncryptionError<E>> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload { msg: plaintext, aad: &associated_data(position) };
+        let ciphertext = self.cipher().encrypt(&nonce, payload)
+            .map_err(|_| EncryptionError::IOError(std::io::Error::new(ErrorKind::InvalidInput, "Journal entry can't be encrypted")))?;
+        let mut entry = nonce.to_vec();
+        entry.extend_from_slice(&ciphertext);
+        Ok(entry)
+    }
+
+    /// Decrypt the `entry` at `position`, which starts with its nonce.
+    fn decrypt<E>(&self, entry: &[u8], position: EntryPosition) -> Result<Vec<u8>, EncryptionError<E>> {
+        if entry.len() < NONCE_SIZE {
+            return Err(EncryptionError::Decryption);
+        }
+        let (nonce, ciphertext) = entry.split_at(NONCE_SIZE);
+        let payload = Payload { msg: ciphertext, aad: &associated_data(position) };
+        self.cipher().decrypt(XNonce::from_slice(nonce), payload)
+            .map_err(|_| EncryptionError::Decryption)
+    }
+}
+
+/// The data the authentication tag of the entry at `position` covers besides the entry.
+fn associated_data(position: EntryPosition) -> [u8; 16] {
+    let mut data = [0; 16];
+    data[..8].copy_from_slice(&position.journal_id.to_le_bytes());
+    data[8..].copy_from_slice(&position.offset.to_le_bytes());
+    data
+}
+
+impl<S: Debug> Debug for Encrypted<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never print the key
+        f.debug_struct("Encrypted")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The error of an [`Encrypted`](Encrypted) serializer or deserializer.
+#[derive(Debug)]
+pub enum EncryptionError<E> {
+    /// The wrapped serializer or deserializer failed.
+    Serialization(E),
+    /// The entry couldn't be encrypted or written.
+    IOError(std::io::Error),
+    /// The entry was modified or the key is wrong.
+    Decryption,
+}
+
+impl<E: Display> Display for EncryptionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::Serialization(err) => write!(f, "{}", err),
+            EncryptionError::IOError(err) => write!(f, "encryption failed: {}", err),
+            EncryptionError::Decryption => write!(f, "entry was tampered with or the key is wrong"),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for EncryptionError<E> {}
+
+impl<T, S> JournalSerialize<T> for Encrypted<S>
+where S: JournalSerialize<T> {
+    type Error = EncryptionError<S::Error>;
+    const FORMAT_ID: &'static str = S::FORMAT_ID;
+    const CODEC_ID: Option<&'static str> = S::CODEC_ID;
+    const CIPHER_ID: Option<&'static str> = Some("xchacha20poly1305");
+    const VERSIONED: bool = S::VERSIONED;
+    const LAYERS: Layers = S::LAYERS.wrap(Layer::Cipher);
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        self.serialize_at(value, EntryPosition::default(), writer)
+    }
+
+    fn serialize_at(&self, value: T, position: EntryPosition, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        let mut plaintext = Vec::new();
+        self.inner.serialize_at(value, position, &mut plaintext)
+            .map_err(EncryptionError::Serialization)?;
+        let entry = self.encrypt(&plaintext, position)?;
+        writer.write_all(&entry)
+            .map_err(EncryptionError::IOError)
+    }
+}
+
+impl<T, D> JournalDeserialize<T> for Encrypted<D>
+where D: JournalDeserialize<T> {
+    type Error = EncryptionError<D::Error>;
+    const FORMAT_ID: &'static str = D::FORMAT_ID;
+    const CODEC_ID: Option<&'static str> = D::CODEC_ID;
+    const CIPHER_ID: Option<&'static str> = Some("xchacha20poly1305");
+    const VERSIONED: bool = D::VERSIONED;
+    const LAYERS: Layers = D::LAYERS.wrap(Layer::Cipher);
+    const DEPENDS_ON_POSITION: bool = true;
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        self.deserialize_at(EntryPosition::default(), reader)
+    }
+
+    fn deserialize_at(&self, position: EntryPosition, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        let mut entry = Vec::new();
+        reader.read_to_end(&mut entry)
+            .map_err(EncryptionError::IOError)?;
+        let plaintext = self.decrypt(&entry, position)?;
+        self.inner.deserialize_at(position, &mut plaintext.as_slice())
+            .map_err(EncryptionError::Serialization)
+    }
+
+    fn relocate(&self, entry: Vec<u8>, from: EntryPosition, to: EntryPosition) -> Result<Vec<u8>, Self::Error> {
+        let plaintext = self.decrypt(&entry, from)?;
+        let plaintext = self.inner.relocate(plaintext, from, to)
+            .map_err(EncryptionError::Serialization)?;
+        self.encrypt(&plaintext, to)
+    }
+
+    fn without_versions(self) -> Option<Self> {
+        let Self { inner, key } = self;
+        inner.without_versions()
+            .map(|inner| Self { inner, key })
+    }
+}
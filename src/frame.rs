@@ -0,0 +1,475 @@
+use std::io::{Read, Seek, BufReader, ErrorKind, Error};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+use crate::{CountingIO, JournalError};
+
+/// Every frame starts with the payload length and the checksum, both as little endian u32.
+pub(crate) const FRAME_HEADER_SIZE: usize = 8;
+
+/// Set in the length prefix of frames that contain a transaction marker instead of an entry.
+const MARKER_FLAG: u32 = 1 << 31;
+
+const MARKER_BEGIN: u8 = 1;
+const MARKER_COMMIT: u8 = 2;
+
+/// The size of the frame that starts a transaction, its entries follow right after it.
+pub(crate) const BEGIN_MARKER_SIZE: u64 = FRAME_HEADER_SIZE as u64 + 1 + 4;
+
+const CRC32C: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+const CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// The checksum that is stored with every entry.
+///
+/// The checksum covers the length prefix and the payload of a frame, so a
+/// corrupted length is detected as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Checksum {
+    /// CRC-32C (Castagnoli), the default.
+    #[default]
+    Crc32c,
+    /// CRC-32 as used by zlib and ethernet.
+    Crc32,
+    /// Don't verify entries at all, the checksum field is always zero.
+    None,
+}
+
+impl Checksum {
+    pub(crate) fn compute(self, length: &[u8], payload: &[u8]) -> u32 {
+        let crc = match self {
+            Checksum::Crc32c => &CRC32C,
+            Checksum::Crc32 => &CRC32,
+            Checksum::None => return 0,
+        };
+        let mut digest = crc.digest();
+        digest.update(length);
+        digest.update(payload);
+        digest.finalize()
+    }
+
+    /// The id that is stored in the file header.
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc32c => 1,
+            Checksum::Crc32 => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Checksum::None),
+            1 => Some(Checksum::Crc32c),
+            2 => Some(Checksum::Crc32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum FrameError {
+    IOError(Error),
+    /// The file ends inside of the frame.
+    Torn,
+    /// The frame is complete, but its checksum doesn't match.
+    Corrupted,
+}
+
+impl From<Error> for FrameError {
+    fn from(err: Error) -> Self {
+        FrameError::IOError(err)
+    }
+}
+
+impl FrameError {
+    /// Attach the offset of the frame that failed.
+    pub(crate) fn at<E>(self, offset: u64) -> JournalError<E> {
+        match self {
+            FrameError::IOError(err) => JournalError::IOError(err),
+            FrameError::Torn => JournalError::IOError(dirty_error()),
+            FrameError::Corrupted => JournalError::Corrupted { offset },
+        }
+    }
+}
+
+/// Marks the boundaries of a transaction.
+///
+/// A transaction is written as begin marker, its entries and a commit marker.
+/// Entries of a transaction without commit marker are never returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Marker {
+    /// The next `count` entries belong to one transaction.
+    Begin { count: u32 },
+    /// All entries of the transaction were written.
+    Commit,
+}
+
+impl Marker {
+    fn encode(self) -> Vec<u8> {
+        match self {
+            Marker::Begin { count } => {
+                let mut payload = vec![MARKER_BEGIN];
+                payload.extend_from_slice(&count.to_le_bytes());
+                payload
+            },
+            Marker::Commit => vec![MARKER_COMMIT],
+        }
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        match payload {
+            [MARKER_BEGIN, a, b, c, d] => Some(Marker::Begin { count: u32::from_le_bytes([*a, *b, *c, *d]) }),
+            [MARKER_COMMIT] => Some(Marker::Commit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Frame {
+    Entry(Vec<u8>),
+    Marker(Marker),
+}
+
+pub(crate) fn dirty_error() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "Journal file is dirty")
+}
+
+/// Reserve space for the frame header at the end of `buffer`.
+/// The payload has to be appended afterwards and the frame finished with
+/// [`finish_frame`](finish_frame).
+pub(crate) fn begin_frame(buffer: &mut Vec<u8>) -> usize {
+    let start = buffer.len();
+    buffer.extend_from_slice(&[0u8; FRAME_HEADER_SIZE]);
+    start
+}
+
+/// Write the header of the frame starting at `start`.
+pub(crate) fn finish_frame(buffer: &mut [u8], start: usize, checksum: Checksum) -> Result<(), Error> {
+    let payload_len = buffer.len() - start - FRAME_HEADER_SIZE;
+    let length = u32::try_from(payload_len).ok()
+        .filter(|length| length & MARKER_FLAG == 0)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Journal entry is too large"))?;
+    write_frame_header(buffer, start, length, checksum);
+    Ok(())
+}
+
+/// Append a frame that contains `marker` to `buffer`.
+pub(crate) fn write_marker(buffer: &mut Vec<u8>, marker: Marker, checksum: Checksum) {
+    let start = begin_frame(buffer);
+    buffer.extend_from_slice(&marker.encode());
+    let length = (buffer.len() - start - FRAME_HEADER_SIZE) as u32 | MARKER_FLAG;
+    write_frame_header(buffer, start, length, checksum);
+}
+
+fn write_frame_header(buffer: &mut [u8], start: usize, length: u32, checksum: Checksum) {
+    let length = length.to_le_bytes();
+    let crc = checksum.compute(&length, &buffer[start + FRAME_HEADER_SIZE..]);
+    buffer[start..start + 4].copy_from_slice(&length);
+    buffer[start + 4..start + FRAME_HEADER_SIZE].copy_from_slice(&crc.to_le_bytes());
+}
+
+/// Read as many bytes as possible into `buf`, stopping early only at EOF.
+fn read_full<R: Read + ?Sized>(reader: &mut R, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(count) => filled += count,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => {},
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+/// Read the header of the next frame.
+///
+/// Returns `Ok(None)` on a clean EOF and `Torn` if the header itself was
+/// only written partially.
+fn read_frame_header<R: Read + ?Sized>(reader: &mut R) -> Result<Option<[u8; FRAME_HEADER_SIZE]>, FrameError> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    match read_full(reader, &mut header)? {
+        0 => Ok(None),
+        FRAME_HEADER_SIZE => Ok(Some(header)),
+        _ => Err(FrameError::Torn),
+    }
+}
+
+/// The payload length and whether the frame contains a marker.
+fn frame_length(header: &[u8; FRAME_HEADER_SIZE]) -> (u32, bool) {
+    let length = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    (length & !MARKER_FLAG, length & MARKER_FLAG != 0)
+}
+
+/// Read the next frame, verify its checksum and return its payload.
+pub(crate) fn read_frame<R: Read + ?Sized>(reader: &mut R, checksum: Checksum) -> Result<Option<Frame>, FrameError> {
+    let header = match read_frame_header(reader)? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let (length, is_marker) = frame_length(&header);
+    let length = length as usize;
+
+    // don't trust the length prefix for the allocation, it might be garbage
+    let mut payload = Vec::with_capacity(length.min(64 * 1024));
+    if reader.take(length as u64).read_to_end(&mut payload)? != length {
+        return Err(FrameError::Torn);
+    }
+
+    let expected = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if checksum.compute(&header[..4], &payload) != expected {
+        return Err(FrameError::Corrupted);
+    }
+    if is_marker {
+        Marker::decode(&payload)
+            .map(|marker| Some(Frame::Marker(marker)))
+            .ok_or(FrameError::Corrupted)
+    } else {
+        Ok(Some(Frame::Entry(payload)))
+    }
+}
+
+/// A frame that borrows its payload from the journal data, see [`frame_at`](frame_at).
+#[derive(Debug)]
+pub(crate) enum SliceFrame<'d> {
+    Entry(&'d [u8]),
+    Marker(Marker),
+}
+
+/// Verify the frame at the start of `data` and return it together with its size.
+///
+/// Returns `Ok(None)` if `data` is empty and `Torn` if it ends inside of the frame.
+pub(crate) fn frame_at(data: &[u8], checksum: Checksum) -> Result<Option<(SliceFrame<'_>, usize)>, FrameError> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+    let header = match data.get(..FRAME_HEADER_SIZE) {
+        Some(header) => <[u8; FRAME_HEADER_SIZE]>::try_from(header).unwrap(),
+        None => return Err(FrameError::Torn),
+    };
+    let (length, is_marker) = frame_length(&header);
+    let payload = data[FRAME_HEADER_SIZE..].get(..length as usize)
+        .ok_or(FrameError::Torn)?;
+
+    let expected = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if checksum.compute(&header[..4], payload) != expected {
+        return Err(FrameError::Corrupted);
+    }
+    let frame = if is_marker {
+        SliceFrame::Marker(Marker::decode(payload).ok_or(FrameError::Corrupted)?)
+    } else {
+        SliceFrame::Entry(payload)
+    };
+    Ok(Some((frame, FRAME_HEADER_SIZE + payload.len())))
+}
+
+/// Verify the entry frame at the start of `data` and return its payload without copying it.
+#[cfg(feature = "mmap")]
+pub(crate) fn entry_at(data: &[u8], checksum: Checksum) -> Result<&[u8], FrameError> {
+    match frame_at(data, checksum)? {
+        Some((SliceFrame::Entry(payload), _)) => Ok(payload),
+        Some((SliceFrame::Marker(_), _)) => Err(FrameError::Corrupted),
+        None => Err(FrameError::Torn),
+    }
+}
+
+/// The payload length of the frame whose header starts at `data`.
+pub(crate) fn payload_length(data: &[u8]) -> Option<usize> {
+    let header = <[u8; FRAME_HEADER_SIZE]>::try_from(data.get(..FRAME_HEADER_SIZE)?).unwrap();
+    Some(frame_length(&header).0 as usize)
+}
+
+/// The offset and the payload of an entry.
+pub(crate) type RawEntry = (u64, Vec<u8>);
+
+/// Reads the entries of a journal one after another.
+///
+/// The entries of a transaction are held back until its commit marker was
+/// read, so either all of them are returned or none.
+#[derive(Debug, Default)]
+pub(crate) struct EntryReader {
+    /// Entries of the last transaction that weren't returned yet
+    pending: VecDeque<RawEntry>,
+}
+
+impl EntryReader {
+    /// Returns the offset and the payload of the next entry.
+    ///
+    /// Errors come with the offset of the frame that failed. If the frame
+    /// belongs to a transaction, this is the offset of the whole transaction.
+    pub(crate) fn next<R: Read>(&mut self, reader: &mut CountingIO<R>, checksum: Checksum) -> Result<Option<RawEntry>, (FrameError, u64)> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                return Ok(Some(entry));
+            }
+            let offset = reader.position().unwrap();
+            match read_frame(reader, checksum).map_err(|err| (err, offset))? {
+                Some(Frame::Entry(payload)) => return Ok(Some((offset, payload))),
+                Some(Frame::Marker(Marker::Begin { count })) => {
+                    self.pending = read_transaction(reader, checksum, count)
+                        .map_err(|err| (err, offset))?;
+                },
+                // the reader started inside of a transaction
+                Some(Frame::Marker(Marker::Commit)) => {},
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+/// Read the entries of a transaction up to its commit marker.
+///
+/// A corrupted transaction is still read to its end, so reading can continue after it.
+fn read_transaction<R: Read>(reader: &mut CountingIO<R>, checksum: Checksum, count: u32) -> Result<VecDeque<RawEntry>, FrameError> {
+    let mut entries = VecDeque::new();
+    let mut corrupted = false;
+    for _ in 0..count {
+        let offset = reader.position().unwrap();
+        match read_frame(reader, checksum) {
+            Ok(Some(Frame::Entry(payload))) => entries.push_back((offset, payload)),
+            Ok(Some(Frame::Marker(_))) | Err(FrameError::Corrupted) => corrupted = true,
+            Ok(None) => return Err(FrameError::Torn),
+            Err(err) => return Err(err),
+        }
+    }
+    match read_frame(reader, checksum) {
+        Ok(Some(Frame::Marker(Marker::Commit))) if !corrupted => Ok(entries),
+        Ok(Some(_)) | Err(FrameError::Corrupted) => Err(FrameError::Corrupted),
+        Ok(None) => Err(FrameError::Torn),
+        Err(err) => Err(err),
+    }
+}
+
+/// Like [`EntryReader`](EntryReader), but for a journal that is completely in memory.
+/// The returned payloads borrow from the journal data.
+#[derive(Debug)]
+pub(crate) struct SliceEntryReader {
+    /// The offset of the next frame
+    position: usize,
+}
+
+impl SliceEntryReader {
+    pub(crate) fn new(position: usize) -> Self {
+        Self { position }
+    }
+
+    /// Returns the payload of the next entry in `data`.
+    pub(crate) fn next<'d>(&mut self, data: &'d [u8], checksum: Checksum) -> Result<Option<&'d [u8]>, (FrameError, u64)> {
+        loop {
+            let offset = self.position;
+            let rest = &data[offset..];
+            let (frame, size) = match frame_at(rest, checksum) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(None),
+                Err(err) => {
+                    self.position += skipped_size(rest, &err);
+                    return Err((err, offset as u64));
+                },
+            };
+            self.position += size;
+            match frame {
+                SliceFrame::Entry(payload) => return Ok(Some(payload)),
+                SliceFrame::Marker(Marker::Begin { count }) => {
+                    // only return entries of a transaction once it is known to be complete
+                    if let Err((err, size)) = check_transaction(&data[self.position..], checksum, count) {
+                        self.position += size;
+                        return Err((err, offset as u64));
+                    }
+                },
+                // the commit marker of the current transaction or one without a begin marker
+                SliceFrame::Marker(Marker::Commit) => {},
+            }
+        }
+    }
+}
+
+/// The number of bytes to skip after reading a frame from `data` failed with `err`.
+fn skipped_size(data: &[u8], err: &FrameError) -> usize {
+    match err {
+        // corrupted frames are complete
+        FrameError::Corrupted => FRAME_HEADER_SIZE + payload_length(data).unwrap(),
+        _ => data.len(),
+    }
+}
+
+/// Make sure `data` starts with `count` valid entries and a commit marker.
+///
+/// Errors come with the size of the transaction, so reading can continue after it.
+fn check_transaction(data: &[u8], checksum: Checksum, count: u32) -> Result<(), (FrameError, usize)> {
+    let mut position = 0;
+    let mut corrupted = false;
+    for _ in 0..count {
+        let rest = &data[position..];
+        match frame_at(rest, checksum) {
+            Ok(Some((SliceFrame::Entry(_), size))) => position += size,
+            Ok(Some((SliceFrame::Marker(_), size))) => {
+                corrupted = true;
+                position += size;
+            },
+            Err(FrameError::Corrupted) => {
+                corrupted = true;
+                position += skipped_size(rest, &FrameError::Corrupted);
+            },
+            Ok(None) | Err(_) => return Err((FrameError::Torn, data.len())),
+        }
+    }
+    let rest = &data[position..];
+    match frame_at(rest, checksum) {
+        Ok(Some((SliceFrame::Marker(Marker::Commit), _))) if !corrupted => Ok(()),
+        Ok(Some((_, size))) => Err((FrameError::Corrupted, position + size)),
+        Err(FrameError::Corrupted) => Err((FrameError::Corrupted, position + skipped_size(rest, &FrameError::Corrupted))),
+        Ok(None) | Err(_) => Err((FrameError::Torn, data.len())),
+    }
+}
+
+/// Walk over all frames between `start` and `end` without reading the payloads of entries.
+/// The reader has to be positioned at `start`. Returns the start offsets of all entries.
+///
+/// An unfinished transaction is treated like a partially written entry.
+pub(crate) fn scan_frames<R: Read + Seek>(reader: &mut BufReader<R>, start: u64, end: u64) -> Result<Vec<u64>, Error> {
+    let mut offsets = Vec::new();
+    // the entries of the current transaction and how many are still missing
+    let mut transaction: Option<(Vec<u64>, u32)> = None;
+    let mut offset = start;
+
+    while offset < end {
+        let (length, is_marker) = match read_frame_header(reader) {
+            Ok(Some(header)) => frame_length(&header),
+            Ok(None) => break,
+            Err(FrameError::IOError(err)) => return Err(err),
+            Err(_) => return Err(dirty_error()),
+        };
+        let next_offset = offset + FRAME_HEADER_SIZE as u64 + length as u64;
+        if next_offset > end {
+            return Err(dirty_error());
+        }
+
+        if is_marker {
+            let mut payload = vec![0u8; length as usize];
+            reader.read_exact(&mut payload)?;
+            match Marker::decode(&payload) {
+                Some(Marker::Begin { count }) => transaction = Some((Vec::new(), count)),
+                Some(Marker::Commit) => if let Some((entries, 0)) = transaction.take() {
+                    offsets.extend(entries);
+                },
+                None => return Err(Error::new(ErrorKind::InvalidData, "Invalid transaction marker")),
+            }
+        } else {
+            reader.seek_relative(length as i64)?;
+            match transaction {
+                Some((ref mut entries, ref mut missing)) if *missing > 0 => {
+                    entries.push(offset);
+                    *missing -= 1;
+                },
+                _ => offsets.push(offset),
+            }
+        }
+        offset = next_offset;
+    }
+
+    if transaction.is_some() {
+        return Err(dirty_error());
+    }
+    Ok(offsets)
+}
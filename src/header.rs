@@ -0,0 +1,522 @@
+use std::io::{Read, Write, Seek, SeekFrom, ErrorKind};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+
+use crate::*;
+
+/// Every journal file starts with these bytes.
+const MAGIC: [u8; 8] = *b"JRNLFILE";
+
+/// The version of the on-disk format that is written by this crate.
+pub(crate) const FORMAT_VERSION: u16 = 1;
+
+/// Magic bytes, format version and length of the header fields.
+const FIXED_SIZE: usize = 8 + 2 + 4;
+
+const TAG_FORMAT: u8 = 1;
+const TAG_CHECKSUM: u8 = 2;
+const TAG_FINGERPRINT: u8 = 3;
+const TAG_CODEC: u8 = 4;
+const TAG_CIPHER: u8 = 5;
+const TAG_VERSIONED: u8 = 6;
+const TAG_JOURNAL_ID: u8 = 7;
+const TAG_LAYERS: u8 = 8;
+
+const HEADER_CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
+
+/// The reason why a file header was rejected.
+#[derive(Debug)]
+pub enum HeaderError {
+    /// The file doesn't start with the magic bytes of a journal.
+    NotAJournal,
+    /// The file was written with a format version this crate can't read.
+    UnsupportedVersion(u16),
+    /// The file was written with another serializer.
+    FormatMismatch { expected: String, found: String },
+    /// The file was written for another entry type.
+    TypeMismatch { expected: TypeFingerprint, found: Option<TypeFingerprint> },
+    /// The entries were compressed with another codec, or not at all.
+    CodecMismatch { expected: Option<String>, found: Option<String> },
+    /// The entries were encrypted with another cipher, or not at all.
+    CipherMismatch { expected: Option<String>, found: Option<String> },
+    /// The entries carry a schema version but none was expected, or the other way around.
+    VersioningMismatch { expected: bool },
+    /// The same wrappers were applied to the entries, but in another order.
+    LayerOrderMismatch { expected: Layers, found: Layers },
+    /// The header is truncated, fails its checksum or contains unknown fields.
+    Malformed(&'static str),
+}
+
+/// Identifies the type of the entries stored in a journal.
+///
+/// A fingerprint is stored in the header when a journal is created and
+/// verified by every reader that expects one, so a journal can't be read
+/// back as a different type by accident.
+///
+/// The fingerprint is only as good as its name and version: it doesn't look
+/// at the type itself, so a changed schema is only detected if the version
+/// was bumped with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeFingerprint {
+    name: String,
+    version: u32,
+}
+
+impl TypeFingerprint {
+    /// A fingerprint from a user supplied name and version.
+    ///
+    /// Bump the version whenever the serialized representation of the type changes.
+    pub fn new<N: Into<String>>(name: N, version: u32) -> Self {
+        Self {
+            name: name.into(),
+            version,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buffer = self.version.to_le_bytes().to_vec();
+        buffer.extend_from_slice(self.name.as_bytes());
+        buffer
+    }
+
+    fn decode(value: &[u8]) -> Option<Self> {
+        if value.len() < 4 {
+            return None;
+        }
+        let version = u32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+        let name = String::from_utf8(value[4..].to_vec()).ok()?;
+        Some(Self::new(name, version))
+    }
+}
+
+/// A wrapper that transforms the serialized entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Layer {
+    Codec = 1,
+    #[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+    Cipher = 2,
+    Versioned = 3,
+}
+
+/// The order in which wrappers like [`Compressed`](crate::codec::Compressed)
+/// and [`VersionedSerializer`](crate::versioning::VersionedSerializer) were
+/// applied to the entries.
+///
+/// The same wrappers in another order write different bytes, so the order is
+/// recorded in the header and verified by every reader.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Layers(u64);
+
+impl Layers {
+    /// The entries are written by the serializer as they are.
+    pub const NONE: Self = Layers(0);
+
+    /// These layers wrapped in one more `layer`.
+    pub(crate) const fn wrap(self, layer: Layer) -> Self {
+        Layers(self.0 << 8 | layer as u64)
+    }
+
+    /// These layers without `layer`.
+    fn without(self, layer: Layer) -> Self {
+        let bytes = self.to_bytes().into_iter()
+            .filter(|&byte| byte != layer as u8)
+            .collect::<Vec<_>>();
+        Self::from_bytes(&bytes).unwrap()
+    }
+
+    /// The layers from the innermost to the outermost one.
+    fn to_bytes(self) -> Vec<u8> {
+        self.0.to_be_bytes().iter().copied().skip_while(|&layer| layer == 0).collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > 8 || bytes.contains(&0) {
+            return None;
+        }
+        Some(Layers(bytes.iter().fold(0, |layers, &layer| layers << 8 | u64::from(layer))))
+    }
+}
+
+impl Debug for Layers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.to_bytes().into_iter().map(|layer| match layer {
+                1 => "codec",
+                2 => "cipher",
+                3 => "versioned",
+                _ => "unknown",
+            }))
+            .finish()
+    }
+}
+
+/// The header at the start of every journal file.
+///
+/// After the magic bytes and the format version follows a list of fields,
+/// each stored as tag, length and value. This allows adding new fields
+/// without breaking the layout.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FileHeader {
+    /// Identifies the serializer the entries were written with
+    pub(crate) format: String,
+    /// The checksum stored with every entry
+    pub(crate) checksum: Checksum,
+    /// Identifies the entry type, if the journal was created with one
+    pub(crate) fingerprint: Option<TypeFingerprint>,
+    /// Identifies the codec the entries were compressed with, if any
+    pub(crate) codec: Option<String>,
+    /// Identifies the cipher the entries were encrypted with, if any
+    pub(crate) cipher: Option<String>,
+    /// Whether every entry starts with its schema version
+    pub(crate) versioned: bool,
+    /// Random id of the journal, which ties a sidecar index to it
+    pub(crate) journal_id: Option<u64>,
+    /// The order of the codec, cipher and versioning wrappers
+    pub(crate) layers: Layers,
+}
+
+/// Where an entry is stored in a journal.
+///
+/// Serializers get it with every entry, so they can bind the entry to its
+/// place, like [`Encrypted`](crate::encryption::Encrypted) does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct EntryPosition {
+    /// The random id of the journal file, every segment of a
+    /// [`SegmentedJournal`](crate::segmented_journal::SegmentedJournal) has
+    /// its own. 0 if the header doesn't have one.
+    pub journal_id: u64,
+    /// The offset of the frame of the entry in the file
+    pub offset: u64,
+}
+
+/// A new random journal id.
+fn random_journal_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(time) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(time.as_nanos());
+    }
+    hasher.finish()
+}
+
+fn malformed<E>(reason: &'static str) -> JournalError<E> {
+    JournalError::InvalidHeader(HeaderError::Malformed(reason))
+}
+
+impl FileHeader {
+    pub(crate) fn new(format: &str, checksum: Checksum) -> Self {
+        Self {
+            format: format.to_string(),
+            checksum,
+            fingerprint: None,
+            codec: None,
+            cipher: None,
+            versioned: false,
+            journal_id: Some(random_journal_id()),
+            layers: Layers::NONE,
+        }
+    }
+
+    /// The header of a journal whose entries are written with `S`.
+    pub(crate) fn for_serializer<T, S: JournalSerialize<T>>(checksum: Checksum) -> Self {
+        Self {
+            codec: S::CODEC_ID.map(str::to_string),
+            cipher: S::CIPHER_ID.map(str::to_string),
+            versioned: S::VERSIONED,
+            layers: S::LAYERS,
+            ..Self::new(S::FORMAT_ID, checksum)
+        }
+    }
+
+    /// The header of a journal whose entries can be read with `D`, the checksum doesn't matter.
+    pub(crate) fn for_deserializer<T, D: JournalDeserialize<T>>() -> Self {
+        Self {
+            codec: D::CODEC_ID.map(str::to_string),
+            cipher: D::CIPHER_ID.map(str::to_string),
+            versioned: D::VERSIONED,
+            layers: D::LAYERS,
+            ..Self::new(D::FORMAT_ID, Checksum::default())
+        }
+    }
+
+    /// The offset of the first entry.
+    pub(crate) fn size(&self) -> u64 {
+        self.encode().len() as u64
+    }
+
+    /// The position of the entry at `offset` in this journal.
+    pub(crate) fn position(&self, offset: u64) -> EntryPosition {
+        EntryPosition { journal_id: self.journal_id.unwrap_or(0), offset }
+    }
+
+    /// The expected header of a journal whose entries carry no schema version.
+    pub(crate) fn without_versions(self) -> Self {
+        Self {
+            versioned: false,
+            layers: self.layers.without(Layer::Versioned),
+            ..self
+        }
+    }
+
+    /// The same header for a rewritten copy of the journal.
+    pub(crate) fn with_new_journal_id(mut self) -> Self {
+        self.journal_id = Some(random_journal_id());
+        self
+    }
+
+    /// Attach a type fingerprint.
+    pub(crate) fn with_fingerprint(mut self, fingerprint: Option<TypeFingerprint>) -> Self {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    fn fields(&self) -> Vec<(u8, Vec<u8>)> {
+        let mut fields = vec![
+            (TAG_FORMAT, self.format.as_bytes().to_vec()),
+            (TAG_CHECKSUM, vec![self.checksum.id()]),
+        ];
+        if let Some(ref fingerprint) = self.fingerprint {
+            fields.push((TAG_FINGERPRINT, fingerprint.encode()));
+        }
+        if let Some(ref codec) = self.codec {
+            fields.push((TAG_CODEC, codec.as_bytes().to_vec()));
+        }
+        if let Some(ref cipher) = self.cipher {
+            fields.push((TAG_CIPHER, cipher.as_bytes().to_vec()));
+        }
+        if self.versioned {
+            fields.push((TAG_VERSIONED, Vec::new()));
+        }
+        if let Some(journal_id) = self.journal_id {
+            fields.push((TAG_JOURNAL_ID, journal_id.to_le_bytes().to_vec()));
+        }
+        if self.layers != Layers::NONE {
+            fields.push((TAG_LAYERS, self.layers.to_bytes()));
+        }
+        fields
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (tag, value) in self.fields() {
+            body.push(tag);
+            body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            body.extend_from_slice(&value);
+        }
+
+        let mut buffer = Vec::with_capacity(FIXED_SIZE + body.len() + 4);
+        buffer.extend_from_slice(&MAGIC);
+        buffer.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buffer.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        buffer.extend_from_slice(&body);
+        let crc = HEADER_CRC.checksum(&buffer);
+        buffer.extend_from_slice(&crc.to_le_bytes());
+        buffer
+    }
+
+    fn decode<E>(body: &[u8]) -> Result<Self, JournalError<E>> {
+        let mut format = None;
+        let mut checksum = None;
+        let mut fingerprint = None;
+        let mut codec = None;
+        let mut cipher = None;
+        let mut versioned = false;
+        let mut journal_id = None;
+        let mut layers = Layers::NONE;
+
+        let mut rest = body;
+        while !rest.is_empty() {
+            if rest.len() < 3 {
+                return Err(malformed("truncated header field"));
+            }
+            let tag = rest[0];
+            let length = u16::from_le_bytes([rest[1], rest[2]]) as usize;
+            if rest.len() < 3 + length {
+                return Err(malformed("truncated header field"));
+            }
+            let value = &rest[3..3 + length];
+            rest = &rest[3 + length..];
+
+            match tag {
+                TAG_FORMAT => format = Some(String::from_utf8(value.to_vec())
+                    .map_err(|_| malformed("serializer id is not valid UTF-8"))?),
+                TAG_CHECKSUM => checksum = Some(value.first()
+                    .and_then(|id| Checksum::from_id(*id))
+                    .ok_or_else(|| malformed("unknown checksum"))?),
+                TAG_FINGERPRINT => fingerprint = Some(TypeFingerprint::decode(value)
+                    .ok_or_else(|| malformed("invalid type fingerprint"))?),
+                TAG_CODEC => codec = Some(String::from_utf8(value.to_vec())
+                    .map_err(|_| malformed("codec id is not valid UTF-8"))?),
+                TAG_CIPHER => cipher = Some(String::from_utf8(value.to_vec())
+                    .map_err(|_| malformed("cipher id is not valid UTF-8"))?),
+                TAG_VERSIONED => versioned = true,
+                TAG_JOURNAL_ID => journal_id = Some(<[u8; 8]>::try_from(value)
+                    .map(u64::from_le_bytes)
+                    .map_err(|_| malformed("invalid journal id"))?),
+                TAG_LAYERS => layers = Layers::from_bytes(value)
+                    .ok_or_else(|| malformed("invalid layers"))?,
+                _ => return Err(malformed("unknown header field")),
+            }
+        }
+
+        Ok(Self {
+            format: format.ok_or_else(|| malformed("missing serializer id"))?,
+            checksum: checksum.ok_or_else(|| malformed("missing checksum"))?,
+            fingerprint,
+            codec,
+            cipher,
+            versioned,
+            journal_id,
+            layers,
+        })
+    }
+
+    /// Read the header at the start of `file`.
+    ///
+    /// Returns `None` if the file is empty. The file position is undefined afterwards.
+    pub(crate) fn read<R, E>(file: &mut R) -> Result<Option<Self>, JournalError<E>>
+    where R: Read + Seek {
+        let length = file.seek(SeekFrom::End(0))
+            .map_err(JournalError::IOError)?;
+        if length == 0 {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(0))
+            .map_err(JournalError::IOError)?;
+
+        let mut fixed = [0u8; FIXED_SIZE];
+        read_header_bytes(file, &mut fixed)?;
+        if fixed[..8] != MAGIC {
+            return Err(JournalError::InvalidHeader(HeaderError::NotAJournal));
+        }
+        let version = u16::from_le_bytes([fixed[8], fixed[9]]);
+        if version != FORMAT_VERSION {
+            return Err(JournalError::InvalidHeader(HeaderError::UnsupportedVersion(version)));
+        }
+        let body_length = u32::from_le_bytes([fixed[10], fixed[11], fixed[12], fixed[13]]) as u64;
+        if FIXED_SIZE as u64 + body_length + 4 > length {
+            return Err(malformed("truncated header"));
+        }
+
+        let mut rest = vec![0u8; body_length as usize + 4];
+        read_header_bytes(file, &mut rest)?;
+        let (body, crc) = rest.split_at(body_length as usize);
+
+        let mut digest = HEADER_CRC.digest();
+        digest.update(&fixed);
+        digest.update(body);
+        if digest.finalize().to_le_bytes() != crc {
+            return Err(malformed("header checksum mismatch"));
+        }
+
+        Self::decode(body).map(Some)
+    }
+
+    /// Check if `file` only contains the beginning of a header, as left
+    /// behind by a crash while the journal was created.
+    pub(crate) fn is_torn<R>(file: &mut R) -> std::io::Result<bool>
+    where R: Read + Seek {
+        let length = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut fixed = Vec::with_capacity(FIXED_SIZE);
+        file.take(FIXED_SIZE as u64).read_to_end(&mut fixed)?;
+        let magic_length = fixed.len().min(MAGIC.len());
+        if fixed[..magic_length] != MAGIC[..magic_length] {
+            return Ok(false);
+        }
+        if fixed.len() < FIXED_SIZE {
+            return Ok(true);
+        }
+        let body_length = u32::from_le_bytes([fixed[10], fixed[11], fixed[12], fixed[13]]) as u64;
+        Ok(length < FIXED_SIZE as u64 + body_length + 4)
+    }
+
+    /// Make sure the entries are encoded the way `expected` describes:
+    /// same serializer, codec, cipher and schema versioning, applied in the same order.
+    pub(crate) fn verify_format<E>(&self, expected: &Self) -> Result<(), JournalError<E>> {
+        let mismatch = if self.format != expected.format {
+            HeaderError::FormatMismatch { expected: expected.format.clone(), found: self.format.clone() }
+        } else if self.codec != expected.codec {
+            HeaderError::CodecMismatch { expected: expected.codec.clone(), found: self.codec.clone() }
+        } else if self.cipher != expected.cipher {
+            HeaderError::CipherMismatch { expected: expected.cipher.clone(), found: self.cipher.clone() }
+        } else if self.versioned != expected.versioned {
+            HeaderError::VersioningMismatch { expected: expected.versioned }
+        } else if self.layers != expected.layers {
+            HeaderError::LayerOrderMismatch { expected: expected.layers, found: self.layers }
+        } else {
+            return Ok(());
+        };
+        Err(JournalError::InvalidHeader(mismatch))
+    }
+
+    /// Make sure the entries can be read with `deserializer` and return the
+    /// deserializer to read them with.
+    ///
+    /// A journal without schema versions can be read by a versioned
+    /// deserializer, if it treats the entries as an old version.
+    pub(crate) fn verify_deserializer<T, D>(&self, deserializer: D) -> Result<D, JournalError<D::Error>>
+    where D: JournalDeserialize<T> {
+        let expected = Self::for_deserializer::<T, D>();
+        if expected.versioned && !self.versioned {
+            if let Some(deserializer) = deserializer.clone().without_versions() {
+                self.verify_format(&expected.without_versions())?;
+                return Ok(deserializer);
+            }
+        }
+        self.verify_format(&expected)?;
+        Ok(deserializer)
+    }
+
+    /// Make sure the entries were written for the expected type.
+    ///
+    /// Passes if no fingerprint is expected.
+    pub(crate) fn verify_fingerprint<E>(&self, expected: Option<&TypeFingerprint>) -> Result<(), JournalError<E>> {
+        match expected {
+            Some(expected) if self.fingerprint.as_ref() != Some(expected) => {
+                Err(JournalError::InvalidHeader(HeaderError::TypeMismatch {
+                    expected: expected.clone(),
+                    found: self.fingerprint.clone(),
+                }))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Read and verify the header of `file`, or write `header` if the file is still empty.
+    pub(crate) fn read_or_write<F, E>(file: &mut F, header: Self) -> Result<Self, JournalError<E>>
+    where F: Read + Write + Seek {
+        match Self::read(file)? {
+            Some(existing) => {
+                existing.verify_format(&header)?;
+                existing.verify_fingerprint(header.fingerprint.as_ref())?;
+                Ok(existing)
+            },
+            None => {
+                file.seek(SeekFrom::Start(0))
+                    .and_then(|_| file.write_all(&header.encode()))
+                    .map_err(JournalError::IOError)?;
+                Ok(header)
+            },
+        }
+    }
+}
+
+fn read_header_bytes<R, E>(file: &mut R, buffer: &mut [u8]) -> Result<(), JournalError<E>>
+where R: Read {
+    file.read_exact(buffer).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => malformed("truncated header"),
+        _ => JournalError::IOError(err),
+    })
+}
@@ -1,107 +1,243 @@
 use std::io::{Seek, SeekFrom, ErrorKind, BufReader};
 use std::fs::File;
 use std::marker::PhantomData;
+use std::ops::{Bound, Range, RangeBounds};
 use std::fmt::Debug;
 
 use crate::*;
+use crate::header::FileHeader;
+use crate::journal_index::JournalIndex;
 use crate::journal_writer::*;
 use crate::journal_reader::*;
 
 #[derive(Debug)]
-pub struct IndexedJournal<'a, T, S, D> {
-    file_handle: Option<OwnedOrRef<'a, File>>,
+pub struct IndexedJournal<'a, T, S, D, F = File> {
+    file_handle: Option<OwnedOrRef<'a, F>>,
     serializer: S,
     deserializer: D,
+    /// The checksum for new journals
+    checksum: Checksum,
+    /// The type fingerprint for new journals
+    fingerprint: Option<TypeFingerprint>,
+    /// The header of the file, `None` as long as the file is empty
+    header: Option<FileHeader>,
     index: JournalIndex,
+    /// The sidecar file the index is persisted in
+    index_file: Option<OwnedOrRef<'a, F>>,
+    sync_tracker: SyncTracker,
+    /// Where a failed write started, if its partial frames are still in the file
+    torn_offset: Option<u64>,
+    /// Set if the entries carry no schema version, but are read with a versioned deserializer
+    unversioned: bool,
+    /// Entries are loaded from this map instead of the file, see [`with_mmap`](IndexedJournal::with_mmap)
+    #[cfg(feature = "mmap")]
+    mapped: Option<crate::mmap::MappedFile>,
     type_phantom: PhantomData<*const T>,
 } 
 
-#[derive(Debug)]
-struct JournalIndex {
-    entry_indices: Vec<u64>,
-}
-
-impl JournalIndex {
-    fn entry_offset(&self, entry_index: usize) -> Result<u64, ()> {
-        if entry_index >= self.entry_indices.len() {
-            Err(())
-        } else {
-            Ok(self.entry_indices[entry_index])
-        }
+impl<'a, T, F> IndexedJournal<'a, T, BincodeSerializer, BincodeDeserializer, F>
+where T: serde::Serialize + for<'de> serde::Deserialize<'de> + Debug, F: Storage {
+    pub fn new<FILE>(file_handle: FILE) -> Result<Self, JournalError<<BincodeDeserializer as JournalDeserialize<T>>::Error>> 
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        Self::with_serializer(file_handle, BincodeSerializer, BincodeDeserializer)
     }
 
-    fn build<D, T>(file: &mut File, deserializer: &D) -> Result<Self, JournalError<D::Error>>
-    where D: JournalDeserialize<T> + Debug,
-          T: Debug {
-
-        JournalReader::with_deserializer(file, *deserializer)
-            .iter_entries()
-            .map(|entry_result| {
-                entry_result.map(|entry| entry.offset)
-            })
-            .collect::<Result<Vec<u64>, JournalError<D::Error>>>()
-            .map(|entry_indices| {
-                Self {
-                    entry_indices,
-                }
-            })
-   }
-}
+    /// Like [`new`](IndexedJournal::new), but the index is loaded from and
+    /// saved to `index_handle`, see [`with_index_file`](IndexedJournal::with_index_file).
+    pub fn new_with_index_file<FILE, INDEX>(file_handle: FILE, index_handle: INDEX) -> Result<Self, JournalError<bincode::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a,
+          INDEX: Into<OwnedOrRef<'a, F>> + 'a {
+        Self::with_index_file(file_handle, index_handle, BincodeSerializer, BincodeDeserializer)
+    }
 
-impl<'a, T> IndexedJournal<'a, T, BincodeSerializer, BincodeDeserializer>
-where T: serde::Serialize + for<'de> serde::Deserialize<'de> + Debug {
-    pub fn new<FILE>(file_handle: FILE) -> Result<Self, JournalError<<BincodeDeserializer as JournalDeserialize<T>>::Error>> 
-    where FILE: Into<OwnedOrRef<'a, File>> + 'a {
-        Self::with_serializer(file_handle, BincodeSerializer, BincodeDeserializer)
+    /// Like [`new`](IndexedJournal::new), but a partially written tail that was
+    /// left behind by a crash is truncated before the index is built.
+    pub fn recover<FILE>(file_handle: FILE) -> Result<(Self, Option<TruncatedTail>), JournalError<bincode::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        Self::recover_with_serializer(file_handle, BincodeSerializer, BincodeDeserializer)
     }
 }
 
 
-impl<'a, T, S, D> IndexedJournal<'a, T, S, D>
+impl<'a, T, S, D, F> IndexedJournal<'a, T, S, D, F>
 where S: JournalSerialize<T> + Debug,
       D: JournalDeserialize<T> + Debug,
-      T: Debug {
+      T: Debug,
+      F: Storage {
     pub fn with_serializer<FILE>(file_handle: FILE, serializer: S, deserializer: D) -> Result<Self, JournalError<D::Error>> 
-    where FILE: Into<OwnedOrRef<'a, File>> + 'a {
-        let mut file_handle = file_handle.into();
-        Ok(Self {
-            index: JournalIndex::build(file_handle.as_mut(), &deserializer)?,
-            type_phantom: PhantomData::default(),
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        Self::open(file_handle.into(), None, serializer, deserializer)
+    }
+
+    fn open(mut file_handle: OwnedOrRef<'a, F>, mut index_file: Option<OwnedOrRef<'a, F>>, serializer: S, mut deserializer: D) -> Result<Self, JournalError<D::Error>> {
+        let header = FileHeader::read(file_handle.as_mut())?;
+        let unversioned = header.as_ref().is_some_and(|header| D::VERSIONED && !header.versioned);
+        if let Some(ref header) = header {
+            deserializer = header.verify_deserializer(deserializer)?;
+            let expected = FileHeader::for_serializer::<T, S>(header.checksum);
+            // the journal can be read, but new entries would be read as the oldest version
+            header.verify_format(&if unversioned { expected.without_versions() } else { expected })?;
+        }
+
+        let index = match index_file {
+            Some(ref mut index_file) => {
+                let mut index = JournalIndex::load(file_handle.as_mut(), header.as_ref(), index_file.as_mut())
+                    .map_err(JournalError::IOError)?;
+                if let Some(ref header) = header {
+                    index.persist(index_file.as_mut(), header)
+                        .map_err(JournalError::IOError)?;
+                }
+                index
+            },
+            None => JournalIndex::build(file_handle.as_mut(), header.as_ref())
+                .map_err(JournalError::IOError)?,
+        };
+
+        let mut journal = Self::from_parts(file_handle, index_file, header, index, serializer, deserializer);
+        journal.unversioned = unversioned;
+        Ok(journal)
+    }
+
+    /// Create a journal in a file that is known to be empty.
+    pub(crate) fn create(file_handle: OwnedOrRef<'a, F>, serializer: S, deserializer: D) -> Self {
+        Self::from_parts(file_handle, None, None, JournalIndex::empty(), serializer, deserializer)
+    }
+
+    fn from_parts(file_handle: OwnedOrRef<'a, F>, index_file: Option<OwnedOrRef<'a, F>>, header: Option<FileHeader>,
+                  index: JournalIndex, serializer: S, deserializer: D) -> Self {
+        Self {
+            index,
+            index_file,
+            sync_tracker: SyncTracker::new(Durability::default()),
+            torn_offset: None,
+            unversioned: false,
+            #[cfg(feature = "mmap")]
+            mapped: None,
+            type_phantom: PhantomData,
             serializer,
             deserializer,
+            checksum: Checksum::default(),
+            fingerprint: None,
+            header,
             file_handle: Some(file_handle),
-        })
+        }
+    }
+
+    /// Keep the index in a sidecar file next to the journal, e.g. `journal.idx`.
+    ///
+    /// Instead of scanning the whole journal, the offsets stored in the
+    /// sidecar file are checked against the journal and only the entries
+    /// appended since then are scanned. The index is rebuilt if the sidecar
+    /// file is empty or doesn't match the journal.
+    pub fn with_index_file<FILE, INDEX>(file_handle: FILE, index_handle: INDEX, serializer: S, deserializer: D) -> Result<Self, JournalError<D::Error>> 
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a,
+          INDEX: Into<OwnedOrRef<'a, F>> + 'a {
+        Self::open(file_handle.into(), Some(index_handle.into()), serializer, deserializer)
     }
 
-    pub fn iter<'outer>(&'outer mut self) -> IndexedJournalIter<'a, 'outer, T, S, D> {
+    /// Like [`recover`](IndexedJournal::recover), but you can provide your own serializer and deserializer.
+    pub fn recover_with_serializer<FILE>(file_handle: FILE, serializer: S, deserializer: D) -> Result<(Self, Option<TruncatedTail>), JournalError<D::Error>> 
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        let mut file_handle = file_handle.into();
+        let tail = recovery::truncate_torn_tail(file_handle.as_mut())?;
+        Ok((Self::with_serializer(file_handle, serializer, deserializer)?, tail))
+    }
+
+    /// Select the checksum that entries are written with.
+    /// Defaults to [`Checksum::Crc32c`](Checksum::Crc32c).
+    ///
+    /// This only has an effect on new journals, existing journals keep the
+    /// checksum that is stored in their header.
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Refuse to open the journal if it wasn't created with `fingerprint`.
+    ///
+    /// New journals store the fingerprint in their header.
+    pub fn with_fingerprint(mut self, fingerprint: TypeFingerprint) -> Result<Self, JournalError<D::Error>> {
+        if let Some(ref header) = self.header {
+            header.verify_fingerprint(Some(&fingerprint))?;
+        }
+        self.fingerprint = Some(fingerprint);
+        Ok(self)
+    }
+
+    /// Select when stored entries are synced to stable storage.
+    /// Defaults to [`Durability::Never`](Durability::Never).
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.sync_tracker = SyncTracker::new(durability);
+        self
+    }
+
+    pub fn iter<'outer>(&'outer mut self) -> IndexedJournalIter<'a, 'outer, T, S, D, F> {
         IndexedJournalIter {
             buf_reader: None,
+            entry_reader: Default::default(),
             outer: self,
             seek: true,
             type_phantom: Default::default(),
         }
     }
 
+    /// Iterate over the entries from the newest to the oldest.
+    ///
+    /// Every entry is looked up in the index, so this doesn't read the entries before.
+    pub fn iter_rev<'outer>(&'outer mut self) -> IndexedJournalRevIter<'a, 'outer, T, S, D, F> {
+        IndexedJournalRevIter {
+            indices: 0..self.len(),
+            outer: self,
+        }
+    }
+
+    /// The number of entries in the journal.
+    pub fn len(&self) -> usize {
+        self.index.entry_indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The size of the file up to the end of the last entry.
+    pub(crate) fn data_end(&self) -> u64 {
+        self.index.end()
+    }
+
     pub fn load_entry(&mut self, index: usize) -> Result<T, JournalError<D::Error>> {
         let offset = self.index.entry_offset(index)
             .map_err(|()| JournalError::IndexOutOfBounds)?;
+
+        #[cfg(feature = "mmap")]
+        if let Some(ref mut mapped) = self.mapped {
+            let header = self.header.as_ref().unwrap();
+            return match mapped.entry(offset, header.checksum) {
+                Ok(payload) => decode_entry(&self.deserializer, header.position(offset), payload),
+                Err(err) => Err(err.at(offset)),
+            };
+        }
         
-        self.file_handle.as_mut().unwrap().seek(SeekFrom::Start(offset))
-            .map_err(|err| JournalError::IOError(err))?;
+        let file_handle = self.file_handle.as_mut().unwrap();
+        file_handle.seek(SeekFrom::Start(offset))
+            .map_err(JournalError::IOError)?;
 
-        match self.deserializer.deserialize(self.file_handle.as_mut().unwrap()) {
-            Ok(Some(x)) => Ok(x),
+        // the index is empty as long as there is no header
+        let header = self.header.as_ref().unwrap();
+        match frame::read_frame(file_handle.as_mut(), header.checksum) {
+            Ok(Some(frame::Frame::Entry(payload))) => decode_entry(&self.deserializer, header.position(offset), &payload),
+            Ok(Some(frame::Frame::Marker(_))) => Err(JournalError::Corrupted { offset }),
             Ok(None) => Err(JournalError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Unexpected EOF"))),
-            Err(x) => Err(JournalError::SerializationError(x)),
+            Err(err) => Err(err.at(offset)),
         }
     }
 
-    pub fn iter_from<'outer>(&'outer mut self, index: usize) -> Result<IndexedJournalIter<'a, 'outer, T, S, D>, JournalError<D::Error>> {
+    pub fn iter_from<'outer>(&'outer mut self, index: usize) -> Result<IndexedJournalIter<'a, 'outer, T, S, D, F>, JournalError<D::Error>> {
         let offset = self.index.entry_offset(index)
             .map_err(|()| JournalError::IndexOutOfBounds)?;
         
         self.file_handle.as_mut().unwrap().seek(SeekFrom::Start(offset))
-            .map_err(|err| JournalError::IOError(err))?;
+            .map_err(JournalError::IOError)?;
 
         //let mut reader = JournalReader::with_deserializer(&mut self.file_handle, self.deserializer);
         //reader.seek_on_iter_start(false);
@@ -113,35 +249,265 @@ where S: JournalSerialize<T> + Debug,
         //})
         Ok(IndexedJournalIter {
             buf_reader: None,
+            entry_reader: Default::default(),
             outer: self,
             seek: false,
             type_phantom: Default::default(),
         })
     }
 
+    /// Iterate over the entries in `range`.
+    ///
+    /// The range is resolved against the current number of entries, entries
+    /// that are appended later aren't returned. Fails if the range exceeds the journal.
+    ///
+    /// Every entry is looked up in the index, so there is exactly one result
+    /// per index, even if a corrupted transaction spans several of them.
+    pub fn range<'outer, R>(&'outer mut self, range: R) -> Result<IndexedJournalRange<'a, 'outer, T, S, D, F>, JournalError<D::Error>>
+    where R: RangeBounds<usize> {
+        let indices = resolve_range(&range, 0, self.len());
+        if indices.start > indices.end || indices.end > self.len() {
+            return Err(JournalError::IndexOutOfBounds);
+        }
+
+        Ok(IndexedJournalRange {
+            indices,
+            outer: self,
+        })
+    }
+
+    /// Append an entry and return its index.
+    pub fn store_entry(&mut self, entry: T) -> Result<usize, JournalError<S::Error>> {
+        self.store_entries(std::iter::once(entry))
+            .map(|indices| indices.start)
+    }
+
+    /// Append all entries with a single write operation and return the range of their indices.
+    pub fn store_entries<I>(&mut self, entries: I) -> Result<Range<usize>, JournalError<S::Error>> 
+    where I: Iterator<Item=T> {
+        let written = self.with_writer(|writer| writer.append_entries(entries))?;
+        self.index_written(written)
+    }
+
+    /// Start a transaction. Its entries are written when it is committed and
+    /// discarded if it is dropped before.
+    pub fn begin_transaction(&mut self) -> Result<IndexedTransaction<'_, 'a, T, S, D, F>, JournalError<S::Error>> {
+        // the checksum is only known once the header is written
+        let entries = self.with_writer(|writer| writer.transaction_buffer())?;
+        Ok(IndexedTransaction {
+            journal: self,
+            entries,
+        })
+    }
+
+    /// Flush everything stored so far to stable storage, regardless of the durability policy.
+    pub fn sync(&mut self) -> Result<(), JournalError<S::Error>> {
+        self.sync_tracker.sync(self.file_handle.as_mut().unwrap().as_mut())
+            .map_err(JournalError::NotSynced)
+    }
+}
+
+impl<'a, T, S, D, F> IndexedJournal<'a, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      T: Debug,
+      F: Storage {
+    /// Run `f` with a writer that appends to this journal, writing the header first if necessary.
+    ///
+    /// The writer only lives for this call, so where its last write failed is kept in the journal.
+    fn with_writer<R, W>(&mut self, f: W) -> Result<R, JournalError<S::Error>>
+    where W: FnOnce(&mut JournalWriter<'_, T, S, F>) -> Result<R, JournalError<S::Error>> {
+        if self.unversioned {
+            return Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: true }));
+        }
+        let file_handle = self.file_handle.as_mut().unwrap().as_mut();
+        if self.header.is_none() {
+            self.header = Some(FileHeader::read_or_write(file_handle,
+                FileHeader::for_serializer::<T, S>(self.checksum).with_fingerprint(self.fingerprint.clone()))?);
+        }
+        let mut writer = JournalWriter::with_header(file_handle.into(), self.serializer.clone(), self.header.clone())
+            .with_torn_offset(self.torn_offset);
+        let result = f(&mut writer);
+        self.torn_offset = writer.torn_offset();
+        result
+    }
+
+    /// Add entries that were just written to the index, persist the index and
+    /// sync the entries if necessary. Returns the range of their indices.
+    fn index_written(&mut self, appended: Appended) -> Result<Range<usize>, JournalError<S::Error>> {
+        let Appended { offsets, written } = appended;
+        let file = self.file_handle.as_mut().unwrap().as_mut();
+        // the entries are in the file already, so a failure here must not look like they weren't stored
+        let indexed = self.index.entry_indices.len();
+        let expected = indexed..indexed + offsets.len();
+        let first = self.index.append(file, self.header.as_ref().unwrap(), &offsets, written.clone())
+            .map_err(|error| JournalError::SyncFailed { indices: expected, error })?;
+        let indices = first..first + offsets.len();
+        if let Some(ref mut index_file) = self.index_file {
+            self.index.persist(index_file.as_mut(), self.header.as_ref().unwrap())
+                .map_err(|error| JournalError::SyncFailed { indices: indices.clone(), error })?;
+        }
+        let file = self.file_handle.as_mut().unwrap().as_mut();
+        self.sync_tracker.written(file, offsets.len(), written.end - written.start)
+            .map_err(|error| JournalError::SyncFailed { indices: indices.clone(), error })?;
+        Ok(indices)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<'a, T, S, D> IndexedJournal<'a, T, S, D, File>
+where D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    /// Load entries through a memory map of the file instead of seeking and reading.
+    ///
+    /// [`load_entry`](IndexedJournal::load_entry) deserializes straight from
+    /// the mapped bytes. The file is mapped again once it grew beyond the map.
+    ///
+    /// The file must not be truncated or modified by anyone else while the
+    /// journal is open, otherwise reading it might crash the process.
+    ///
+    /// Only journals that are stored in a [`File`](std::fs::File) can be
+    /// mapped. Other storages are usually in memory already, use
+    /// [`JournalSlice`](crate::journal_slice::JournalSlice) to read them without copying.
+    pub fn with_mmap(mut self) -> Result<Self, JournalError<D::Error>> {
+        let file = self.file_handle.as_ref().unwrap().try_clone()
+            .map_err(JournalError::IOError)?;
+        self.mapped = Some(crate::mmap::MappedFile::new(file));
+        Ok(self)
+    }
+}
+
+/// A group of entries that is written completely or not at all,
+/// see [`Transaction`](crate::journal_writer::Transaction).
+#[derive(Debug)]
+pub struct IndexedTransaction<'j, 'a, T, S, D, F = File> {
+    journal: &'j mut IndexedJournal<'a, T, S, D, F>,
+    entries: TransactionBuffer,
+}
+
+impl<'j, 'a, T, S, D, F> IndexedTransaction<'j, 'a, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      T: Debug,
+      F: Storage {
     pub fn store_entry(&mut self, entry: T) -> Result<(), JournalError<S::Error>> {
-        let mut writer = JournalWriter::with_serializer(
-            self.file_handle.as_mut().unwrap().as_mut(), self.serializer);
-        writer.store_entry(entry)
+        self.store_entries(std::iter::once(entry))
     }
 
-    pub fn store_entries<I>(&mut self, entries: I) -> Result<(), JournalError<S::Error>> 
+    /// Add entries to the transaction. If one of them can't be serialized,
+    /// none of them is added, but the transaction can still be committed.
+    pub fn store_entries<I>(&mut self, entries: I) -> Result<(), JournalError<S::Error>>
     where I: Iterator<Item=T> {
-        let mut writer = JournalWriter::with_serializer(
-            self.file_handle.as_mut().unwrap().as_mut(), self.serializer);
-        writer.store_entries(entries)
+        let checksum = self.journal.header.as_ref().unwrap().checksum;
+        self.entries.push(&self.journal.serializer, checksum, entries)
     }
+
+    /// Write all entries of the transaction and return the range of their indices.
+    pub fn commit(self) -> Result<Range<usize>, JournalError<S::Error>> {
+        let entries = self.entries;
+        let written = self.journal.with_writer(|writer| writer.append_transaction(entries))?;
+        self.journal.index_written(written)
+    }
+}
+
+/// Turn `range` into the indices it contains, unbounded ends are `first` and `len`.
+/// The result isn't checked against the journal.
+pub(crate) fn resolve_range<R>(range: &R, first: usize, len: usize) -> Range<usize>
+where R: RangeBounds<usize> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.saturating_add(1),
+        Bound::Unbounded => first,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.saturating_add(1),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    start..end
 }
 
-impl<'inner, 'outer, T, S, D> IntoIterator for &'outer mut IndexedJournal<'inner, T, S, D>
+/// Iterates over a range of entries, see [`range`](IndexedJournal::range).
+pub struct IndexedJournalRange<'inner, 'outer, T, S, D, F = File> {
+    outer: &'outer mut IndexedJournal<'inner, T, S, D, F>,
+    /// The indices of the entries that weren't returned yet
+    indices: Range<usize>,
+}
+
+impl<'inner, 'outer, T, S, D, F> Iterator for IndexedJournalRange<'inner, 'outer, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug,
+      F: Storage {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.indices.next()
+            .map(|index| self.outer.load_entry(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<'inner, 'outer, T, S, D, F> ExactSizeIterator for IndexedJournalRange<'inner, 'outer, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug,
+      F: Storage {}
+
+/// Iterates over the entries of an [`IndexedJournal`](IndexedJournal) from
+/// the newest to the oldest, see [`iter_rev`](IndexedJournal::iter_rev).
+pub struct IndexedJournalRevIter<'inner, 'outer, T, S, D, F = File> {
+    outer: &'outer mut IndexedJournal<'inner, T, S, D, F>,
+    /// The indices of the entries that weren't returned yet
+    indices: Range<usize>,
+}
+
+impl<'inner, 'outer, T, S, D, F> Iterator for IndexedJournalRevIter<'inner, 'outer, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug,
+      F: Storage {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.indices.next_back()
+            .map(|index| self.outer.load_entry(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<'inner, 'outer, T, S, D, F> DoubleEndedIterator for IndexedJournalRevIter<'inner, 'outer, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug,
+      F: Storage {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.indices.next()
+            .map(|index| self.outer.load_entry(index))
+    }
+}
+
+impl<'inner, 'outer, T, S, D, F> ExactSizeIterator for IndexedJournalRevIter<'inner, 'outer, T, S, D, F>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug,
+      F: Storage {}
+
+impl<'inner, 'outer, T, S, D, F> IntoIterator for &'outer mut IndexedJournal<'inner, T, S, D, F>
 where D: JournalDeserialize<T> + Debug + 'inner,
-      T: Debug + 'inner {
-    type Item = <IndexedJournalIter<'inner, 'outer, T, S, D> as Iterator>::Item;
-    type IntoIter = IndexedJournalIter<'inner, 'outer, T, S, D>;
+      T: Debug + 'inner,
+      F: Storage {
+    type Item = <IndexedJournalIter<'inner, 'outer, T, S, D, F> as Iterator>::Item;
+    type IntoIter = IndexedJournalIter<'inner, 'outer, T, S, D, F>;
 
-    fn into_iter(self) -> IndexedJournalIter<'inner, 'outer, T, S, D> {
+    fn into_iter(self) -> IndexedJournalIter<'inner, 'outer, T, S, D, F> {
         IndexedJournalIter {
             buf_reader: None,
+            entry_reader: Default::default(),
             outer: self,
             seek: true,
             type_phantom: Default::default(),
@@ -154,14 +520,15 @@ where D: JournalDeserialize<T> + Debug + 'inner,
     }
 }
 
-pub struct IndexedJournalIter<'inner, 'outer, T, S, D> {
-    pub(crate) outer: &'outer mut IndexedJournal<'inner, T, S, D>,
-    pub(crate) buf_reader: Option<CountingIO<BufReader<OwnedOrRef<'inner, File>>>>,
+pub struct IndexedJournalIter<'inner, 'outer, T, S, D, F = File> {
+    pub(crate) outer: &'outer mut IndexedJournal<'inner, T, S, D, F>,
+    pub(crate) buf_reader: Option<CountingIO<BufReader<OwnedOrRef<'inner, F>>>>,
+    pub(crate) entry_reader: frame::EntryReader,
     pub(crate) seek: bool,
     pub(crate) type_phantom: PhantomData<*const T>,
 }
 
-impl<'inner, 'outer, T, S, D> Drop for IndexedJournalIter<'inner, 'outer, T, S, D> {
+impl<'inner, 'outer, T, S, D, F> Drop for IndexedJournalIter<'inner, 'outer, T, S, D, F> {
     fn drop(&mut self) {
         if self.outer.file_handle.is_none() {
             let file_handle = self.buf_reader.take().map(|x| x.into_inner().into_inner());
@@ -170,38 +537,39 @@ impl<'inner, 'outer, T, S, D> Drop for IndexedJournalIter<'inner, 'outer, T, S,
     }
 }
 
-impl<'inner, 'outer, T, S, D> Iterator for IndexedJournalIter<'inner, 'outer, T, S, D>
-where D: JournalDeserialize<T> + Debug, T: Debug {
+impl<'inner, 'outer, T, S, D, F> Iterator for IndexedJournalIter<'inner, 'outer, T, S, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
     type Item = Result<T, JournalError<D::Error>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.buf_reader.is_none() {
-            self.buf_reader = Some(CountingIO::new(BufReader::new(self.outer.file_handle.take().unwrap())).without_offset())
+            let data_start = match self.outer.header {
+                Some(ref header) => header.size(),
+                None => return None,
+            };
+            let mut file = self.outer.file_handle.take().unwrap();
+            // iter_from already moved the file to the first requested entry
+            let position = if self.seek {
+                file.seek(SeekFrom::Start(data_start))
+            } else {
+                file.stream_position()
+            };
+            let offset = match position {
+                Ok(offset) => offset,
+                Err(err) => {
+                    self.outer.file_handle = Some(file);
+                    return Some(Err(JournalError::IOError(err)));
+                },
+            };
+            self.buf_reader = Some(CountingIO::new(BufReader::new(file)).with_offset(offset));
         }
 
         let buf_reader = self.buf_reader.as_mut().unwrap();
-
-        if self.seek {
-            buf_reader.seek(SeekFrom::Start(0)).unwrap();
-            self.seek = false;
-        }
-
-        let start_offset = buf_reader.position().unwrap();
-        let result = self.outer.deserializer.deserialize(buf_reader);
-        let end_offset = buf_reader.position().unwrap();
-
-        match result {
-            Ok(Some(value)) => Some(Ok(value)),
-            Ok(None) => {
-                // we are at EOF, but we want to make shure that there were no trailing bytes,
-                // since that would mean the last write operation wasn't successful
-                if start_offset == end_offset {
-                    None
-                } else {
-                    Some(Err(JournalError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Journal file is dirty"))))
-                }
-            },
-            Err(err) => Some(Err(JournalError::SerializationError(err)))
+        let header = self.outer.header.as_ref().unwrap();
+        match self.entry_reader.next(buf_reader, header.checksum) {
+            Ok(Some((offset, payload))) => Some(decode_entry(&self.outer.deserializer, header.position(offset), &payload)),
+            Ok(None) => None,
+            Err((err, offset)) => Some(Err(err.at(offset))),
         }
     }
 }
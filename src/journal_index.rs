@@ -0,0 +1,235 @@
+use std::io::{Read, Write, Seek, SeekFrom, BufReader, BufWriter, ErrorKind};
+use std::ops::Range;
+use std::convert::TryInto;
+
+use crate::{frame, Storage};
+use crate::header::FileHeader;
+
+/// Every sidecar index file starts with these bytes.
+const INDEX_MAGIC: [u8; 8] = *b"JRNLIDX\0";
+
+/// The version of the sidecar index format.
+const INDEX_VERSION: u16 = 2;
+
+/// Magic bytes, version, the checksum of the journal header and the end of
+/// the indexed data, followed by one little endian u64 offset per entry.
+const INDEX_HEADER_SIZE: u64 = 8 + 2 + 4 + 8;
+
+/// Where the end of the indexed data is stored in the sidecar file.
+const INDEX_END_OFFSET: u64 = 8 + 2 + 4;
+
+/// The content of a sidecar index file.
+struct Sidecar {
+    /// The checksum of the header of the journal the index was written for
+    journal_header: u32,
+    /// The end of the indexed data in the journal
+    end: u64,
+    entry_indices: Vec<u64>,
+}
+
+/// Identifies the journal a sidecar file belongs to. The header of every
+/// journal contains a random id and ends with its checksum.
+fn header_checksum(header: &FileHeader) -> u32 {
+    let encoded = header.encode();
+    u32::from_le_bytes(encoded[encoded.len() - 4..].try_into().unwrap())
+}
+
+/// The byte offsets of all entries in a journal.
+#[derive(Debug)]
+pub(crate) struct JournalIndex {
+    pub(crate) entry_indices: Vec<u64>,
+    /// The end of the last indexed entry
+    end: u64,
+    /// How many offsets are stored in the sidecar index file
+    persisted: usize,
+}
+
+impl JournalIndex {
+    pub(crate) fn entry_offset(&self, entry_index: usize) -> Result<u64, ()> {
+        if entry_index >= self.entry_indices.len() {
+            Err(())
+        } else {
+            Ok(self.entry_indices[entry_index])
+        }
+    }
+
+    /// Walk over the frames of the file without deserializing any entry.
+    pub(crate) fn build<F: Storage>(file: &mut F, header: Option<&FileHeader>) -> std::io::Result<Self> {
+        let mut index = Self::empty();
+        if let Some(header) = header {
+            index.extend(file, header.size())?;
+        }
+        Ok(index)
+    }
+
+    /// Load the index from a sidecar file and add the entries that were
+    /// appended since it was written.
+    ///
+    /// The index is rebuilt from scratch if the sidecar file is empty, belongs
+    /// to another journal or doesn't match the journal anymore.
+    pub(crate) fn load<F: Storage>(file: &mut F, header: Option<&FileHeader>, index_file: &mut F) -> std::io::Result<Self> {
+        let header = match header {
+            Some(header) => header,
+            None => return Ok(Self::empty()),
+        };
+
+        let sidecar = match Self::read_sidecar(index_file)? {
+            Some(sidecar) => sidecar,
+            None => return Self::build(file, Some(header)),
+        };
+        if !Self::matches(file, header, &sidecar)? {
+            return Self::build(file, Some(header));
+        }
+        let Sidecar { end, entry_indices, .. } = sidecar;
+        let persisted = entry_indices.len();
+        let mut index = Self { entry_indices, end, persisted };
+        // the journal was modified behind our back, e.g. truncated and appended to
+        if index.extend(file, end).is_err() {
+            return Self::build(file, Some(header));
+        }
+        Ok(index)
+    }
+
+    /// Check that the sidecar file was written for this journal and that the
+    /// journal still contains all the data it indexes.
+    fn matches<F: Storage>(file: &mut F, header: &FileHeader, sidecar: &Sidecar) -> std::io::Result<bool> {
+        let end = sidecar.end;
+        if sidecar.journal_header != header_checksum(header)
+            || end < header.size() || end > file.seek(SeekFrom::End(0))? {
+            return Ok(false);
+        }
+        let last = match (sidecar.entry_indices.first(), sidecar.entry_indices.last()) {
+            (Some(&first), Some(&last)) => {
+                if first != header.size() || last >= end {
+                    return Ok(false);
+                }
+                last
+            },
+            _ => return Ok(true),
+        };
+        if sidecar.entry_indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Ok(false);
+        }
+        Ok(Self::end_of_entry(file, last, header)?.filter(|&entry_end| entry_end <= end).is_some())
+    }
+
+    /// The end of the last indexed entry, 0 if there is none.
+    pub(crate) fn end(&self) -> u64 {
+        self.end
+    }
+
+    pub(crate) fn empty() -> Self {
+        Self {
+            entry_indices: Vec::new(),
+            end: 0,
+            persisted: 0,
+        }
+    }
+
+    /// Add the offsets of all entries from `start` to the end of the file.
+    fn extend<F: Storage>(&mut self, file: &mut F, start: u64) -> std::io::Result<()> {
+        let end = file.seek(SeekFrom::End(0))?;
+        self.extend_to(file, start, end)
+    }
+
+    fn extend_to<F: Storage>(&mut self, file: &mut F, start: u64, end: u64) -> std::io::Result<()> {
+        file.seek(SeekFrom::Start(start))?;
+        let offsets = frame::scan_frames(&mut BufReader::new(file), start, end)?;
+        self.entry_indices.extend(offsets);
+        self.end = end;
+        Ok(())
+    }
+
+    /// Add entries that were just written to the file.
+    ///
+    /// `written` is the range of bytes that contains the new entries. Entries
+    /// that were appended by someone else in the meantime are indexed first.
+    /// Returns the index of the first new entry.
+    pub(crate) fn append<F: Storage>(&mut self, file: &mut F, header: &FileHeader, offsets: &[u64], written: Range<u64>) -> std::io::Result<usize> {
+        let indexed_end = self.end.max(header.size());
+        if written.start > indexed_end {
+            self.extend_to(file, indexed_end, written.start)?;
+        }
+        let first_index = self.entry_indices.len();
+        self.entry_indices.extend_from_slice(offsets);
+        self.end = written.end;
+        Ok(first_index)
+    }
+
+    /// Returns the end of the entry at `offset` or `None` if there is no valid entry.
+    fn end_of_entry<F: Storage>(file: &mut F, offset: u64, header: &FileHeader) -> std::io::Result<Option<u64>> {
+        if offset < header.size() || offset >= file.seek(SeekFrom::End(0))? {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        match frame::read_frame(file, header.checksum) {
+            Ok(Some(_)) => Ok(Some(file.stream_position()?)),
+            Ok(None) | Err(frame::FrameError::Torn) | Err(frame::FrameError::Corrupted) => Ok(None),
+            Err(frame::FrameError::IOError(err)) => Err(err),
+        }
+    }
+
+    /// Read the sidecar file. A partially written offset at the end is ignored.
+    fn read_sidecar<F: Storage>(index_file: &mut F) -> std::io::Result<Option<Sidecar>> {
+        index_file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(index_file);
+
+        let mut header = [0u8; INDEX_HEADER_SIZE as usize];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {},
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        if header[..8] != INDEX_MAGIC || header[8..10] != INDEX_VERSION.to_le_bytes() {
+            return Ok(None);
+        }
+        let journal_header = u32::from_le_bytes(header[10..14].try_into().unwrap());
+        let end = u64::from_le_bytes(header[14..].try_into().unwrap());
+
+        let mut entry_indices = Vec::new();
+        let mut offset = [0u8; 8];
+        loop {
+            match reader.read_exact(&mut offset) {
+                Ok(()) => entry_indices.push(u64::from_le_bytes(offset)),
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Some(Sidecar { journal_header, end, entry_indices }))
+    }
+
+    /// Write the offsets that are not yet stored in the sidecar file of the journal with `header`.
+    ///
+    /// The sidecar file is rewritten completely if it wasn't loaded successfully.
+    /// The end of the indexed data is updated last, so offsets that were
+    /// written before a crash are beyond it and the sidecar file is rejected.
+    pub(crate) fn persist<F: Storage>(&mut self, index_file: &mut F, header: &FileHeader) -> std::io::Result<()> {
+        let persisted = self.persisted;
+        if persisted > 0 && persisted == self.entry_indices.len() {
+            return Ok(());
+        }
+        let start = INDEX_HEADER_SIZE + persisted as u64 * 8;
+        let mut writer = BufWriter::new(&mut *index_file);
+        if persisted == 0 {
+            writer.seek(SeekFrom::Start(0))?;
+            writer.write_all(&INDEX_MAGIC)?;
+            writer.write_all(&INDEX_VERSION.to_le_bytes())?;
+            writer.write_all(&header_checksum(header).to_le_bytes())?;
+            // not valid until the offsets were written
+            writer.write_all(&0u64.to_le_bytes())?;
+        } else {
+            writer.seek(SeekFrom::Start(start))?;
+        }
+        for offset in &self.entry_indices[persisted..] {
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        writer.seek(SeekFrom::Start(INDEX_END_OFFSET))?;
+        writer.write_all(&self.end.max(header.size()).to_le_bytes())?;
+        writer.flush()?;
+        drop(writer);
+
+        index_file.truncate(INDEX_HEADER_SIZE + self.entry_indices.len() as u64 * 8)?;
+        self.persisted = self.entry_indices.len();
+        Ok(())
+    }
+}
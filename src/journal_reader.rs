@@ -1,30 +1,96 @@
 use std::io::{Read, Seek, SeekFrom, BufReader, ErrorKind};
 use std::fs::File;
 use std::marker::PhantomData;
+use std::time::{Duration, Instant};
 use std::fmt::Debug;
 
 use crate::*;
+use crate::header::FileHeader;
 
 /// You can implement JournalDeserialize yourself to use a custom
 /// data format.
-pub trait JournalDeserialize<T> : Copy + 'static {
+///
+/// Every entry is stored in its own length prefixed frame, so the reader
+/// passed to [`deserialize`](JournalDeserialize::deserialize) only contains
+/// the bytes of a single entry.
+pub trait JournalDeserialize<T> : Clone + 'static {
     /// The error that is returned when deserialization fails.
     type Error: std::error::Error;
+    /// Identifies the data format in the file header, has to match the
+    /// [`JournalSerialize::FORMAT_ID`](crate::journal_writer::JournalSerialize::FORMAT_ID)
+    /// of the serializer the journal was written with.
+    const FORMAT_ID: &'static str = "custom";
+    /// Identifies the codec entries are compressed with, has to match the
+    /// [`JournalSerialize::CODEC_ID`](crate::journal_writer::JournalSerialize::CODEC_ID)
+    /// of the serializer the journal was written with.
+    const CODEC_ID: Option<&'static str> = None;
+    /// Identifies the cipher entries are encrypted with, like [`CODEC_ID`](JournalDeserialize::CODEC_ID).
+    const CIPHER_ID: Option<&'static str> = None;
+    /// Whether every entry starts with its schema version, like [`CODEC_ID`](JournalDeserialize::CODEC_ID).
+    const VERSIONED: bool = false;
+    /// The wrappers around the deserializer in the order they were applied, like [`CODEC_ID`](JournalDeserialize::CODEC_ID).
+    const LAYERS: Layers = Layers::NONE;
+    /// Whether serialized entries depend on their [`EntryPosition`](crate::EntryPosition),
+    /// so [`relocate`](JournalDeserialize::relocate) has to change them. Wrappers
+    /// pass on the value of the deserializer they wrap.
+    const DEPENDS_ON_POSITION: bool = false;
     /// Deserialize the data from an `Read`.
     fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error>;
+
+    /// Like [`deserialize`](JournalDeserialize::deserialize), but gets the position
+    /// the entry was read from, see [`JournalSerialize::serialize_at`](crate::journal_writer::JournalSerialize::serialize_at).
+    fn deserialize_at(&self, position: EntryPosition, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        let _ = position;
+        self.deserialize(reader)
+    }
+
+    /// Turn the serialized entry that was written at position `from` into one
+    /// for position `to`, e.g. when a journal is compacted. Formats that don't
+    /// depend on the position return the entry unchanged.
+    fn relocate(&self, entry: Vec<u8>, from: EntryPosition, to: EntryPosition) -> Result<Vec<u8>, Self::Error> {
+        let _ = (from, to);
+        Ok(entry)
+    }
+
+    /// The deserializer for a journal whose entries carry no schema version
+    /// although [`VERSIONED`](JournalDeserialize::VERSIONED) is set, see
+    /// [`VersionedDeserializer`](crate::versioning::VersionedDeserializer).
+    /// Returns `None` if such a journal can't be read.
+    fn without_versions(self) -> Option<Self> {
+        None
+    }
+}
+
+/// Like [`JournalDeserialize`](JournalDeserialize), but the returned value
+/// may borrow from the bytes of the entry, e.g. `&'de str` or `&'de [u8]`.
+///
+/// This is used by [`JournalSlice`](crate::journal_slice::JournalSlice) to
+/// read entries without allocating.
+pub trait JournalDeserializeBorrowed<'de, T> : Clone + 'static {
+    /// The error that is returned when deserialization fails.
+    type Error: std::error::Error;
+    /// Identifies the data format in the file header, see [`JournalDeserialize::FORMAT_ID`](JournalDeserialize::FORMAT_ID).
+    const FORMAT_ID: &'static str = "custom";
+    /// Deserialize the data of a single entry.
+    fn deserialize_borrowed(&self, bytes: &'de [u8]) -> Result<T, Self::Error>;
 }
 
 /// This struct provides sequential reads from journal files.
 /// 
 /// If you only want to use the default file format, check out [`SimpleJournalReader`](../type.SimpleJournalReader.html).
 /// 
-/// This struct will wrap a [`File`](std::fs::File) or a [`&mut File`](std::fs::File).
+/// This struct will wrap a [`File`](std::fs::File) or a [`&mut File`](std::fs::File),
+/// or any other [`Storage`](crate::Storage) backend.
 #[derive(Debug)]
-pub struct JournalReader<'a, T, D> {
+pub struct JournalReader<'a, T, D, F = File> {
     /// The underlying file handle
-    file_handle: Option<OwnedOrRef<'a, File>>,
+    file_handle: Option<OwnedOrRef<'a, F>>,
     /// The deserializer that is used to deserialize entries
     deserializer: D,
+    /// The header of the file, `None` as long as the file is empty
+    header: Option<FileHeader>,
+    /// The type fingerprint the journal must have been created with
+    fingerprint: Option<TypeFingerprint>,
     /// Phantom data to let this struct compile with type parameter T
     type_phantom: PhantomData<*const T>,
     /// Seek to position 0 on iteration start?
@@ -38,6 +104,7 @@ pub struct BincodeDeserializer;
 impl<T> JournalDeserialize<T> for BincodeDeserializer
 where T: for<'de> serde::Deserialize<'de> {
     type Error = bincode::Error;
+    const FORMAT_ID: &'static str = "bincode";
 
     fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
         use bincode::config::Options;
@@ -62,26 +129,210 @@ where T: for<'de> serde::Deserialize<'de> {
     }
 }
 
-impl<'a, T> JournalReader<'a, T, BincodeDeserializer>
-where T: for<'de> serde::Deserialize<'de> + Debug {
+/// Reads entries written by [`JsonSerializer`](crate::journal_writer::JsonSerializer), requires the `json` feature.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy)]
+pub struct JsonDeserializer;
+
+#[cfg(feature = "json")]
+impl<T> JournalDeserialize<T> for JsonDeserializer
+where T: for<'de> serde::Deserialize<'de> {
+    type Error = serde_json::Error;
+    const FORMAT_ID: &'static str = "json";
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        let mut line = Vec::new();
+        reader.read_to_end(&mut line)
+            .map_err(serde_json::Error::io)?;
+        // a line without its line break was only written partially
+        if line.last() != Some(&b'\n') {
+            return Ok(None);
+        }
+        match serde_json::from_slice(&line) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.is_eof() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Reads entries written by [`MsgPackSerializer`](crate::journal_writer::MsgPackSerializer), requires the `msgpack` feature.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy)]
+pub struct MsgPackDeserializer;
+
+#[cfg(feature = "msgpack")]
+impl<T> JournalDeserialize<T> for MsgPackDeserializer
+where T: for<'de> serde::Deserialize<'de> {
+    type Error = rmp_serde::decode::Error;
+    const FORMAT_ID: &'static str = "msgpack";
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        use rmp_serde::decode::Error;
+        match rmp_serde::decode::from_read(reader) {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::InvalidMarkerRead(ref err) | Error::InvalidDataRead(ref err))
+                if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Reads entries written by [`CborSerializer`](crate::journal_writer::CborSerializer), requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy)]
+pub struct CborDeserializer;
+
+#[cfg(feature = "cbor")]
+impl<T> JournalDeserialize<T> for CborDeserializer
+where T: for<'de> serde::Deserialize<'de> {
+    type Error = ciborium::de::Error<std::io::Error>;
+    const FORMAT_ID: &'static str = "cbor";
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        match ciborium::de::from_reader(reader) {
+            Ok(value) => Ok(Some(value)),
+            Err(ciborium::de::Error::Io(ref err)) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Reads entries written by [`PostcardSerializer`](crate::journal_writer::PostcardSerializer), requires the `postcard` feature.
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy)]
+pub struct PostcardDeserializer;
+
+#[cfg(feature = "postcard")]
+impl<T> JournalDeserialize<T> for PostcardDeserializer
+where T: for<'de> serde::Deserialize<'de> {
+    type Error = PostcardError;
+    const FORMAT_ID: &'static str = "postcard";
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        let mut bytes = Vec::new();
+        // postcard can't read from a stream, but the reader only contains a single entry
+        reader.read_to_end(&mut bytes)
+            .map_err(PostcardError::IOError)?;
+        match postcard::from_bytes(&bytes) {
+            Ok(value) => Ok(Some(value)),
+            Err(postcard::Error::DeserializeUnexpectedEnd) => Ok(None),
+            Err(err) => Err(PostcardError::Postcard(err)),
+        }
+    }
+}
+
+/// The error of a [`PostcardDeserializer`](PostcardDeserializer).
+#[cfg(feature = "postcard")]
+#[derive(Debug)]
+pub enum PostcardError {
+    /// The entry isn't valid postcard data.
+    Postcard(postcard::Error),
+    /// The entry couldn't be read.
+    IOError(std::io::Error),
+}
+
+#[cfg(feature = "postcard")]
+impl std::fmt::Display for PostcardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostcardError::Postcard(err) => write!(f, "{}", err),
+            PostcardError::IOError(err) => write!(f, "entry can't be read: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "postcard")]
+impl std::error::Error for PostcardError {}
+
+impl<'de, T> JournalDeserializeBorrowed<'de, T> for BincodeDeserializer
+where T: serde::Deserialize<'de> {
+    type Error = bincode::Error;
+    const FORMAT_ID: &'static str = "bincode";
+
+    fn deserialize_borrowed(&self, bytes: &'de [u8]) -> Result<T, Self::Error> {
+        use bincode::config::Options;
+        bincode::options()
+            .with_varint_encoding()
+            .allow_trailing_bytes()
+            .deserialize(bytes)
+    }
+}
+
+impl<'a, T, F> JournalReader<'a, T, BincodeDeserializer, F>
+where T: for<'de> serde::Deserialize<'de> + Debug, F: Storage {
     /// Create a new [`JournalReader`](JournalReader) with the default deserializer.
-    pub fn new<FILE>(file_handle: FILE) -> Self
-    where FILE: Into<OwnedOrRef<'a, File>> + 'a {
+    ///
+    /// This fails if the file is not empty and doesn't start with a valid
+    /// journal header.
+    pub fn new<FILE>(file_handle: FILE) -> Result<Self, JournalError<bincode::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
         Self::with_deserializer(file_handle, BincodeDeserializer)
     }
 }
 
-impl<'a, T, D> JournalReader<'a, T, D>
-where D: JournalDeserialize<T> + Debug, T: Debug {
+impl<'a, T, D, F> JournalReader<'a, T, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
     /// Like [`new`](JournalReader::new), but you can provide your own deserializer.
-    pub fn with_deserializer<FILE>(file_handle: FILE, deserializer: D) -> Self
-    where FILE: Into<OwnedOrRef<'a, File>> + 'a {
-        Self {
+    pub fn with_deserializer<FILE>(file_handle: FILE, mut deserializer: D) -> Result<Self, JournalError<D::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        let mut file_handle = file_handle.into();
+        let position = file_handle.stream_position()
+            .map_err(JournalError::IOError)?;
+        let header = Self::read_header(file_handle.as_mut(), None, &mut deserializer)?;
+        file_handle.seek(SeekFrom::Start(position))
+            .map_err(JournalError::IOError)?;
+
+        Ok(Self {
             seek: true,
-            type_phantom: PhantomData::default(),
+            type_phantom: PhantomData,
             deserializer,
-            file_handle: Some(file_handle.into()),
+            header,
+            fingerprint: None,
+            file_handle: Some(file_handle),
+        })
+    }
+
+    /// Refuse to read the journal if it wasn't created with `fingerprint`.
+    ///
+    /// If the file is still empty, the fingerprint is checked as soon as
+    /// the journal was created.
+    pub fn with_fingerprint(mut self, fingerprint: TypeFingerprint) -> Result<Self, JournalError<D::Error>> {
+        if let Some(ref header) = self.header {
+            header.verify_fingerprint(Some(&fingerprint))?;
+        }
+        self.fingerprint = Some(fingerprint);
+        Ok(self)
+    }
+
+    fn read_header(file: &mut F, fingerprint: Option<&TypeFingerprint>, deserializer: &mut D) -> Result<Option<FileHeader>, JournalError<D::Error>> {
+        let header = FileHeader::read(file)?;
+        if let Some(ref header) = header {
+            *deserializer = header.verify_deserializer(deserializer.clone())?;
+            header.verify_fingerprint(fingerprint)?;
+        }
+        Ok(header)
+    }
+
+    /// Move the file to the first entry of a new iteration.
+    ///
+    /// Returns the offset of that entry or `None` if the journal is still empty.
+    fn start_iteration(&mut self, file: &mut F, seek: bool) -> Result<Option<u64>, JournalError<D::Error>> {
+        let position = file.stream_position()
+            .map_err(JournalError::IOError)?;
+        if self.header.is_none() {
+            // the file was empty when this reader was created
+            self.header = Self::read_header(file, self.fingerprint.as_ref(), &mut self.deserializer)?;
         }
+        let data_start = match self.header {
+            Some(ref header) => header.size(),
+            None => return Ok(None),
+        };
+
+        let offset = if seek { data_start } else { position.max(data_start) };
+        file.seek(SeekFrom::Start(offset))
+            .map_err(JournalError::IOError)?;
+        Ok(Some(offset))
     }
 
     /// Configure if you want the reader to seek to the beginning of the file
@@ -95,43 +346,64 @@ where D: JournalDeserialize<T> + Debug, T: Debug {
     }
 
     /// Iterate over the journals entries
-    pub fn iter<'outer>(&'outer mut self) -> JournalReaderIterUnwrapped<'a, 'outer, T, D> {
+    pub fn iter<'outer>(&'outer mut self) -> JournalReaderIterUnwrapped<'a, 'outer, T, D, F> {
         self.into_iter()
     }
 
     /// Same as [`iter`](JournalReader::iter) but also contains byte offsets.
-    pub(crate) fn iter_entries<'outer>(&'outer mut self) -> JournalReaderIter<'a, 'outer, T, D> {
+    #[allow(dead_code)]
+    pub(crate) fn iter_entries<'outer>(&'outer mut self) -> JournalReaderIter<'a, 'outer, T, D, F> {
         JournalReaderIter {
             seek: self.seek,
             reader: OwnedOrRef::Ref(self),
             buf_reader: None,
+            entry_reader: Default::default(),
+        }
+    }
+
+    /// Iterate over the journals entries and wait for new ones at the end, like `tail -f`.
+    ///
+    /// The returned iterator polls the file until another entry was appended
+    /// completely. It only ends if a [`timeout`](JournalReaderFollow::timeout) was set.
+    pub fn follow<'outer>(&'outer mut self) -> JournalReaderFollow<'a, 'outer, T, D, F> {
+        JournalReaderFollow {
+            reader: self,
+            buf_reader: None,
+            entry_reader: Default::default(),
+            offset: 0,
+            rewind: false,
+            poll_interval: Duration::from_millis(100),
+            timeout: None,
         }
     }
 
     /// Turn this reader into an Iterator.
-    pub fn into_iter<'outer>(self) -> JournalReaderIterUnwrapped<'a, 'outer, T, D> {
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter<'outer>(self) -> JournalReaderIterUnwrapped<'a, 'outer, T, D, F> {
         JournalReaderIterUnwrapped(JournalReaderIter {
             seek: self.seek,
             reader: OwnedOrRef::Owned(self),
             buf_reader: None,
+            entry_reader: Default::default(),
         })
     }
 
     /// Unwrap this struct and return the stored file handle.
-    pub fn into_inner(self) -> OwnedOrRef<'a, File> {
+    pub fn into_inner(self) -> OwnedOrRef<'a, F> {
         self.file_handle.unwrap()
     }
 }
 
-pub(crate) struct JournalReaderIter<'inner, 'outer, T, D> {
-    pub(crate) reader: OwnedOrRef<'outer, JournalReader<'inner, T, D>>,
-    pub(crate) buf_reader: Option<CountingIO<BufReader<OwnedOrRef<'inner, File>>>>,
+pub(crate) struct JournalReaderIter<'inner, 'outer, T, D, F> {
+    pub(crate) reader: OwnedOrRef<'outer, JournalReader<'inner, T, D, F>>,
+    pub(crate) buf_reader: Option<CountingIO<BufReader<OwnedOrRef<'inner, F>>>>,
+    pub(crate) entry_reader: frame::EntryReader,
     pub(crate) seek: bool,
 }
 
-pub struct JournalReaderIterUnwrapped<'inner, 'outer, T, D>(JournalReaderIter<'inner, 'outer, T, D>);
+pub struct JournalReaderIterUnwrapped<'inner, 'outer, T, D, F = File>(JournalReaderIter<'inner, 'outer, T, D, F>);
 
-impl<'inner, 'outer, T, D> Drop for JournalReaderIter<'inner, 'outer, T, D> {
+impl<'inner, 'outer, T, D, F> Drop for JournalReaderIter<'inner, 'outer, T, D, F> {
     fn drop(&mut self) {
         if self.reader.as_mut().file_handle.is_none() {
             let file_handle = self.buf_reader.take().map(|x| x.into_inner().into_inner());
@@ -140,91 +412,176 @@ impl<'inner, 'outer, T, D> Drop for JournalReaderIter<'inner, 'outer, T, D> {
     }
 }
 
-impl<'inner, 'outer, T, D> Iterator for JournalReaderIter<'inner, 'outer, T, D>
-where D: JournalDeserialize<T> + Debug, T: Debug {
+impl<'inner, 'outer, T, D, F> Iterator for JournalReaderIter<'inner, 'outer, T, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
     type Item = Result<JournalEntry<T>, JournalError<D::Error>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut reader = if let Some(reader) = self.buf_reader.take() {
             reader
         } else {
-            let mut file = self.reader.as_mut().file_handle.take().unwrap();
-            if self.seek {
-                file.as_mut().seek(SeekFrom::Start(0)).unwrap();
+            let reader = self.reader.as_mut();
+            let mut file = reader.file_handle.take().unwrap();
+            let offset = reader.start_iteration(file.as_mut(), self.seek);
+            match offset {
+                Ok(Some(offset)) => CountingIO::new(BufReader::new(file)).with_offset(offset),
+                Ok(None) => {
+                    reader.file_handle = Some(file);
+                    return None;
+                },
+                Err(err) => {
+                    reader.file_handle = Some(file);
+                    return Some(Err(err));
+                },
             }
-            CountingIO::new(BufReader::new(file)).without_offset()
         };
 
-        let start_offset = reader.position().unwrap();
-        let result = self.reader.as_mut().deserializer.deserialize(&mut reader);
-        let end_offset = reader.position().unwrap();
+        // the header is known once the iteration started
+        let checksum = self.reader.as_ref().header.as_ref().unwrap().checksum;
+        let result = self.entry_reader.next(&mut reader, checksum);
 
         self.buf_reader = Some(reader);
 
         match result {
-            Ok(Some(value)) => Some(Ok(JournalEntry::new(value, start_offset))),
-            Ok(None) => {
-                // we are at EOF, but we want to make shure that there were no trailing bytes,
-                // since that would mean the last write operation wasn't successful
-                if start_offset == end_offset {
-                    None
-                } else {
-                    Some(Err(JournalError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Journal file is dirty"))))
-                }
-            },
-            Err(err) => Some(Err(JournalError::SerializationError(err)))
+            Ok(Some((offset, payload))) => Some(decode_entry(&self.reader.as_ref().deserializer, self.reader.as_ref().header.as_ref().unwrap().position(offset), &payload)
+                .map(|value| JournalEntry::new(value, offset))),
+            Ok(None) => None,
+            Err((err, offset)) => Some(Err(err.at(offset))),
         }
     }
 }
 
-impl<'inner, 'outer, T, D> Iterator for JournalReaderIterUnwrapped<'inner, 'outer, T, D>
-where D: JournalDeserialize<T> + Debug, T: Debug {
+impl<'inner, 'outer, T, D, F> Iterator for JournalReaderIterUnwrapped<'inner, 'outer, T, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
     type Item = Result<T, JournalError<D::Error>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let myself = &mut self.0;
-        let mut reader = if let Some(reader) = myself.buf_reader.take() {
-            reader
-        } else {
-            let mut file = myself.reader.as_mut().file_handle.take().unwrap();
-            if myself.seek {
-                file.as_mut().seek(SeekFrom::Start(0)).unwrap();
-            }
-            CountingIO::new(BufReader::new(file)).without_offset()
-        };
+        self.0.next().map(|result| result.map(|entry| entry.value))
+    }
+}
 
-        let start_offset = reader.position().unwrap();
-        let result = myself.reader.as_mut().deserializer.deserialize(&mut reader);
-        let end_offset = reader.position().unwrap();
+/// Follows a journal while it is written, see [`follow`](JournalReader::follow).
+pub struct JournalReaderFollow<'inner, 'outer, T, D, F = File> {
+    reader: &'outer mut JournalReader<'inner, T, D, F>,
+    buf_reader: Option<CountingIO<BufReader<OwnedOrRef<'inner, F>>>>,
+    entry_reader: frame::EntryReader,
+    /// The end of the last complete frame
+    offset: u64,
+    /// Go back to `offset` before reading again, the file might have been moved meanwhile
+    rewind: bool,
+    poll_interval: Duration,
+    timeout: Option<Duration>,
+}
 
-        myself.buf_reader = Some(reader);
+impl<'inner, 'outer, T, D, F> JournalReaderFollow<'inner, 'outer, T, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
+    /// How long to sleep before checking for new entries again. Defaults to 100ms.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
 
-        match result {
-            Ok(Some(value)) => Some(Ok(value)),
-            Ok(None) => {
-                // we are at EOF, but we want to make shure that there were no trailing bytes,
-                // since that would mean the last write operation wasn't successful
-                if start_offset == end_offset {
-                    None
-                } else {
-                    Some(Err(JournalError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Journal file is dirty"))))
-                }
+    /// End the iteration if no new entry was appended for `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Read the next entry if it was written completely.
+    fn try_next(&mut self) -> Result<Option<T>, JournalError<D::Error>> {
+        if self.buf_reader.is_none() {
+            let mut file = self.reader.file_handle.take().unwrap();
+            match self.reader.start_iteration(file.as_mut(), self.reader.seek) {
+                Ok(Some(offset)) => {
+                    self.offset = offset;
+                    self.buf_reader = Some(CountingIO::new(BufReader::new(file)).with_offset(offset));
+                },
+                Ok(None) => {
+                    self.reader.file_handle = Some(file);
+                    return Ok(None);
+                },
+                // the header is still being written
+                Err(JournalError::InvalidHeader(HeaderError::Malformed(_)))
+                    if FileHeader::is_torn(file.as_mut()).map_err(JournalError::IOError)? => {
+                    self.reader.file_handle = Some(file);
+                    return Ok(None);
+                },
+                Err(err) => {
+                    self.reader.file_handle = Some(file);
+                    return Err(err);
+                },
+            }
+        }
+
+        let reader = self.buf_reader.as_mut().unwrap();
+        if self.rewind {
+            reader.seek(SeekFrom::Start(self.offset))
+                .map_err(JournalError::IOError)?;
+            self.rewind = false;
+        }
+        let header = self.reader.header.as_ref().unwrap();
+        match self.entry_reader.next(reader, header.checksum) {
+            Ok(Some((offset, payload))) => {
+                self.offset = reader.position().unwrap();
+                decode_entry(&self.reader.deserializer, header.position(offset), &payload).map(Some)
+            },
+            // the writer isn't done yet, read the frame again later
+            Ok(None) | Err((frame::FrameError::Torn, _)) => {
+                self.rewind = true;
+                Ok(None)
+            },
+            Err((err, offset)) => {
+                self.offset = reader.position().unwrap();
+                Err(err.at(offset))
             },
-            Err(err) => Some(Err(JournalError::SerializationError(err)))
         }
     }
 }
 
-impl<'inner, 'outer, T, D> IntoIterator for &'outer mut JournalReader<'inner, T, D>
-where D: JournalDeserialize<T> + Debug, T: Debug {
-    type Item = <JournalReaderIterUnwrapped<'inner, 'outer, T, D> as Iterator>::Item;
-    type IntoIter = JournalReaderIterUnwrapped<'inner, 'outer, T, D>;
+impl<'inner, 'outer, T, D, F> Iterator for JournalReaderFollow<'inner, 'outer, T, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            match self.try_next() {
+                Ok(Some(value)) => return Some(Ok(value)),
+                Ok(None) => {},
+                Err(err) => return Some(Err(err)),
+            }
+
+            let sleep = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if remaining > Duration::from_secs(0) => remaining.min(self.poll_interval),
+                    _ => return None,
+                },
+                None => self.poll_interval,
+            };
+            std::thread::sleep(sleep);
+        }
+    }
+}
+
+impl<'inner, 'outer, T, D, F> Drop for JournalReaderFollow<'inner, 'outer, T, D, F> {
+    fn drop(&mut self) {
+        if let Some(buf_reader) = self.buf_reader.take() {
+            self.reader.file_handle = Some(buf_reader.into_inner().into_inner());
+        }
+    }
+}
+
+impl<'inner, 'outer, T, D, F> IntoIterator for &'outer mut JournalReader<'inner, T, D, F>
+where D: JournalDeserialize<T> + Debug, T: Debug, F: Storage {
+    type Item = <JournalReaderIterUnwrapped<'inner, 'outer, T, D, F> as Iterator>::Item;
+    type IntoIter = JournalReaderIterUnwrapped<'inner, 'outer, T, D, F>;
 
     fn into_iter(self) -> Self::IntoIter {
         JournalReaderIterUnwrapped(JournalReaderIter {
             seek: self.seek,
             reader: OwnedOrRef::Ref(self),
             buf_reader: None,
+            entry_reader: Default::default(),
         })
     }
 }
\ No newline at end of file
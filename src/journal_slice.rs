@@ -0,0 +1,107 @@
+use std::io::Cursor;
+use std::marker::PhantomData;
+use std::fmt::Debug;
+
+use crate::*;
+use crate::header::FileHeader;
+
+/// Reads the entries of a journal that is completely in memory, e.g. a
+/// `Vec<u8>` or a memory mapped file.
+///
+/// In contrast to [`JournalReader`](crate::journal_reader::JournalReader),
+/// entries are deserialized straight from the journal data and may borrow
+/// from it, so `&str` and `&[u8]` can be read without allocating.
+///
+/// If you only want to use the default file format, check out [`SimpleJournalSlice`](../type.SimpleJournalSlice.html).
+#[derive(Debug)]
+pub struct JournalSlice<'data, T, D> {
+    data: &'data [u8],
+    deserializer: D,
+    /// The header of the journal, `None` if `data` is empty
+    header: Option<FileHeader>,
+    type_phantom: PhantomData<*const T>,
+}
+
+impl<'data, T> JournalSlice<'data, T, BincodeDeserializer>
+where T: serde::Deserialize<'data> {
+    /// Read the journal in `data` with the default deserializer.
+    ///
+    /// This fails if `data` is not empty and doesn't start with a valid journal header.
+    pub fn new(data: &'data [u8]) -> Result<Self, JournalError<bincode::Error>> {
+        Self::with_deserializer(data, BincodeDeserializer)
+    }
+}
+
+impl<'data, T, D> JournalSlice<'data, T, D>
+where D: JournalDeserializeBorrowed<'data, T> + Debug {
+    /// Like [`new`](JournalSlice::new), but you can provide your own deserializer.
+    pub fn with_deserializer(data: &'data [u8], deserializer: D) -> Result<Self, JournalError<D::Error>> {
+        let header = FileHeader::read(&mut Cursor::new(data))?;
+        if let Some(ref header) = header {
+            header.verify_format(&FileHeader::new(D::FORMAT_ID, header.checksum))?;
+        }
+        Ok(Self {
+            data,
+            deserializer,
+            header,
+            type_phantom: PhantomData,
+        })
+    }
+
+    /// Refuse to read the journal if it wasn't created with `fingerprint`.
+    pub fn with_fingerprint(self, fingerprint: TypeFingerprint) -> Result<Self, JournalError<D::Error>> {
+        if let Some(ref header) = self.header {
+            header.verify_fingerprint(Some(&fingerprint))?;
+        }
+        Ok(self)
+    }
+
+    /// Iterate over the journals entries.
+    pub fn iter(&self) -> JournalSliceIter<'data, T, D> {
+        let (checksum, start) = match self.header {
+            Some(ref header) => (header.checksum, header.size() as usize),
+            None => (Checksum::default(), self.data.len()),
+        };
+        JournalSliceIter {
+            data: self.data,
+            deserializer: self.deserializer.clone(),
+            checksum,
+            entry_reader: frame::SliceEntryReader::new(start),
+            type_phantom: PhantomData,
+        }
+    }
+}
+
+impl<'data, T, D> IntoIterator for &JournalSlice<'data, T, D>
+where D: JournalDeserializeBorrowed<'data, T> + Debug {
+    type Item = Result<T, JournalError<D::Error>>;
+    type IntoIter = JournalSliceIter<'data, T, D>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterates over the entries of a [`JournalSlice`](JournalSlice).
+#[derive(Debug)]
+pub struct JournalSliceIter<'data, T, D> {
+    data: &'data [u8],
+    deserializer: D,
+    checksum: Checksum,
+    entry_reader: frame::SliceEntryReader,
+    type_phantom: PhantomData<*const T>,
+}
+
+impl<'data, T, D> Iterator for JournalSliceIter<'data, T, D>
+where D: JournalDeserializeBorrowed<'data, T> {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.entry_reader.next(self.data, self.checksum) {
+            Ok(Some(payload)) => Some(self.deserializer.deserialize_borrowed(payload)
+                .map_err(JournalError::SerializationError)),
+            Ok(None) => None,
+            Err((err, offset)) => Some(Err(err.at(offset))),
+        }
+    }
+}
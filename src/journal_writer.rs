@@ -1,19 +1,63 @@
-use std::io::{Write, Seek, SeekFrom};
+use std::io::{Write, SeekFrom, ErrorKind};
+use std::convert::TryFrom;
+use std::ops::Range;
 use std::fs::File;
 use std::marker::PhantomData;
 use std::fmt::Debug;
 
 use crate::*;
+use crate::header::FileHeader;
 
-pub trait JournalSerialize<T> : Copy + 'static {
+pub trait JournalSerialize<T> : Clone + 'static {
     type Error: std::error::Error;
+    /// Identifies the data format in the file header. Readers refuse to open
+    /// a journal if their [`JournalDeserialize::FORMAT_ID`](crate::journal_reader::JournalDeserialize::FORMAT_ID)
+    /// doesn't match.
+    const FORMAT_ID: &'static str = "custom";
+    /// Identifies the codec entries are compressed with, see [`Compressed`](crate::codec::Compressed).
+    const CODEC_ID: Option<&'static str> = None;
+    /// Identifies the cipher entries are encrypted with, see [`Encrypted`](crate::encryption::Encrypted).
+    const CIPHER_ID: Option<&'static str> = None;
+    /// Whether every entry starts with its schema version, see [`VersionedSerializer`](crate::versioning::VersionedSerializer).
+    const VERSIONED: bool = false;
+    /// The wrappers around the serializer in the order they were applied, see [`Layers`](crate::Layers).
+    const LAYERS: Layers = Layers::NONE;
     fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error>;
+
+    /// Like [`serialize`](JournalSerialize::serialize), but gets the position the
+    /// entry is written at. Wrappers pass it on and [`Encrypted`](crate::encryption::Encrypted)
+    /// authenticates it, so entries can't be moved to another place or journal.
+    fn serialize_at(&self, value: T, position: EntryPosition, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        let _ = position;
+        self.serialize(value, writer)
+    }
 }
 
+/// Appends entries to a journal.
+///
+/// Entries are framed in memory first and written with a single write
+/// operation per [`store_entries`](JournalWriter::store_entries) call. With
+/// [`with_buffer_capacity`](JournalWriter::with_buffer_capacity) they are kept
+/// in memory until the buffer is full or [`flush`](JournalWriter::flush) is called.
 #[derive(Debug)]
-pub struct JournalWriter<'a, T, S> {
-    file_handle: OwnedOrRef<'a, File>,
+pub struct JournalWriter<'a, T, S, F = File>
+where F: Storage {
+    file_handle: OwnedOrRef<'a, F>,
     serializer: S,
+    checksum: Checksum,
+    /// The type fingerprint for new journals
+    fingerprint: Option<TypeFingerprint>,
+    /// The header of the file, `None` as long as the file is empty
+    header: Option<FileHeader>,
+    /// Entries are framed in this buffer before they are written to the file
+    buffer: Vec<u8>,
+    /// The buffer is written once it grows beyond this size
+    buffer_capacity: usize,
+    /// The number of entries in the buffer
+    buffered_entries: usize,
+    /// Where a failed write started, if its partial frames couldn't be removed yet
+    torn_offset: Option<u64>,
+    sync_tracker: SyncTracker,
     type_phantom: PhantomData<*const T>,
 } 
 
@@ -23,6 +67,7 @@ pub struct BincodeSerializer;
 impl<T> JournalSerialize<T> for BincodeSerializer
 where T: serde::Serialize {
     type Error = bincode::Error;
+    const FORMAT_ID: &'static str = "bincode";
 
     fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
         use bincode::Options;
@@ -32,42 +77,472 @@ where T: serde::Serialize {
     }
 }
 
-impl<'a, T> JournalWriter<'a, T, BincodeSerializer>
-where T: serde::Serialize + Debug {
-    pub fn new<FILE>(file_handle: FILE) -> Self 
-    where FILE: Into<OwnedOrRef<'a, File>> + 'a {
+/// Writes every entry as a single line of JSON, requires the `json` feature.
+///
+/// The lines are still stored in binary frames, so the journal is not a JSON
+/// Lines file that `jq` or `tail` could read. Text tools like `grep` or
+/// `strings` still find the entries. Write plain JSON Lines files with a
+/// [`JsonLinesWriter`](crate::JsonLinesWriter) instead.
+#[cfg(feature = "json")]
+#[derive(Debug, Copy, Clone)]
+pub struct JsonSerializer;
+
+#[cfg(feature = "json")]
+impl<T> JournalSerialize<T> for JsonSerializer
+where T: serde::Serialize {
+    type Error = serde_json::Error;
+    const FORMAT_ID: &'static str = "json";
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        serde_json::to_writer(&mut *writer, &value)?;
+        writer.write_all(b"\n")
+            .map_err(serde_json::Error::io)
+    }
+}
+
+/// Writes entries as MessagePack, requires the `msgpack` feature.
+///
+/// Structs are written as maps with their field names, so other languages
+/// can read them without knowing the field order.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Copy, Clone)]
+pub struct MsgPackSerializer;
+
+#[cfg(feature = "msgpack")]
+impl<T> JournalSerialize<T> for MsgPackSerializer
+where T: serde::Serialize {
+    type Error = rmp_serde::encode::Error;
+    const FORMAT_ID: &'static str = "msgpack";
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        rmp_serde::encode::write_named(writer, &value)
+    }
+}
+
+/// Writes entries as CBOR, requires the `cbor` feature.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Copy, Clone)]
+pub struct CborSerializer;
+
+#[cfg(feature = "cbor")]
+impl<T> JournalSerialize<T> for CborSerializer
+where T: serde::Serialize {
+    type Error = ciborium::ser::Error<std::io::Error>;
+    const FORMAT_ID: &'static str = "cbor";
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        ciborium::ser::into_writer(&value, writer)
+    }
+}
+
+/// Writes entries with postcard, requires the `postcard` feature.
+#[cfg(feature = "postcard")]
+#[derive(Debug, Copy, Clone)]
+pub struct PostcardSerializer;
+
+#[cfg(feature = "postcard")]
+impl<T> JournalSerialize<T> for PostcardSerializer
+where T: serde::Serialize {
+    type Error = postcard::Error;
+    const FORMAT_ID: &'static str = "postcard";
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        postcard::to_io(&value, writer).map(|_| ())
+    }
+}
+
+impl<'a, T, F> JournalWriter<'a, T, BincodeSerializer, F>
+where T: serde::Serialize + Debug, F: Storage {
+    /// Create a new writer with the default serializer.
+    ///
+    /// If the file is not empty, its header is checked. New files get
+    /// their header with the first entry.
+    pub fn new<FILE>(file_handle: FILE) -> Result<Self, JournalError<bincode::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
         Self::with_serializer(file_handle, BincodeSerializer)
     }
+
+    /// Like [`new`](JournalWriter::new), but a partially written tail that was
+    /// left behind by a crash is truncated first.
+    pub fn recover<FILE>(file_handle: FILE) -> Result<(Self, Option<TruncatedTail>), JournalError<bincode::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        Self::recover_with_serializer(file_handle, BincodeSerializer)
+    }
 }
 
-impl<'a, T, S> JournalWriter<'a, T, S>
-where S: JournalSerialize<T> + Debug, T: Debug {
-    pub fn with_serializer<FILE>(file_handle: FILE, serializer: S) -> Self 
-    where FILE: Into<OwnedOrRef<'a, File>> + 'a {
+impl<'a, T, S, F> JournalWriter<'a, T, S, F>
+where S: JournalSerialize<T> + Debug, T: Debug, F: Storage {
+    /// Like [`new`](JournalWriter::new), but you can provide your own serializer.
+    pub fn with_serializer<FILE>(file_handle: FILE, serializer: S) -> Result<Self, JournalError<S::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        let mut file_handle = file_handle.into();
+        let header = FileHeader::read(file_handle.as_mut())?;
+        if let Some(ref header) = header {
+            header.verify_format(&FileHeader::for_serializer::<T, S>(header.checksum))?;
+        }
+        Ok(Self::with_header(file_handle, serializer, header))
+    }
+
+    /// Like [`recover`](JournalWriter::recover), but you can provide your own serializer.
+    pub fn recover_with_serializer<FILE>(file_handle: FILE, serializer: S) -> Result<(Self, Option<TruncatedTail>), JournalError<S::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        let mut file_handle = file_handle.into();
+        let tail = recovery::truncate_torn_tail(file_handle.as_mut())?;
+        Ok((Self::with_serializer(file_handle, serializer)?, tail))
+    }
+
+    /// Create a writer for a file whose header was already checked.
+    pub(crate) fn with_header(file_handle: OwnedOrRef<'a, F>, serializer: S, header: Option<FileHeader>) -> Self {
         Self {
-            type_phantom: PhantomData::default(),
+            type_phantom: PhantomData,
             serializer,
-            file_handle: file_handle.into(),
+            checksum: header.as_ref().map(|header| header.checksum).unwrap_or_default(),
+            fingerprint: None,
+            header,
+            buffer: Vec::new(),
+            buffer_capacity: 0,
+            buffered_entries: 0,
+            torn_offset: None,
+            sync_tracker: SyncTracker::new(Durability::default()),
+            file_handle,
         }
     }
 
+    /// Select the checksum that is stored with every entry.
+    /// Defaults to [`Checksum::Crc32c`](Checksum::Crc32c).
+    ///
+    /// This only has an effect on new journals, existing journals keep the
+    /// checksum that is stored in their header.
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        if self.header.is_none() {
+            self.checksum = checksum;
+        }
+        self
+    }
+
+    /// Store `fingerprint` in the header of a new journal.
+    ///
+    /// Fails if the journal already exists and was created with another fingerprint.
+    pub fn with_fingerprint(mut self, fingerprint: TypeFingerprint) -> Result<Self, JournalError<S::Error>> {
+        if let Some(ref header) = self.header {
+            header.verify_fingerprint(Some(&fingerprint))?;
+        }
+        self.fingerprint = Some(fingerprint);
+        Ok(self)
+    }
+
+    /// Select when written entries are synced to stable storage.
+    /// Defaults to [`Durability::Never`](Durability::Never).
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.sync_tracker = SyncTracker::new(durability);
+        self
+    }
+
+    /// Keep up to `capacity` bytes of entries in memory before they are written.
+    ///
+    /// Buffered entries are written by [`flush`](JournalWriter::flush), once the
+    /// buffer is full or by [`finish`](JournalWriter::finish). They are lost if the
+    /// process dies before or if writing them fails. Defaults to 0, which writes
+    /// every `store_entry` call immediately.
+    pub fn with_buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = capacity;
+        self.buffer.reserve(capacity);
+        self
+    }
+
     pub fn store_entry(&mut self, entry: T) -> Result<(), JournalError<S::Error>> {
-        self.file_handle.seek(SeekFrom::End(0))
-            .map_err(|err| JournalError::IOError(err))?;
-        self.serializer.serialize(entry, self.file_handle.as_mut())
-            .map_err(|err| JournalError::SerializationError(err))
+        self.store_entries(std::iter::once(entry))
     }
 
+    /// Store all entries, they are written together with a single write operation.
     pub fn store_entries<I>(&mut self, entries: I) -> Result<(), JournalError<S::Error>> 
     where I: Iterator<Item=T> {
-        self.file_handle.seek(SeekFrom::End(0))
-            .map_err(|err| JournalError::IOError(err))?;
-        
-        for entry in entries {
-            self.serializer.serialize(entry, self.file_handle.as_mut())
-                .map_err(|err| JournalError::SerializationError(err))?;
+        self.ensure_header()?;
+        let base = self.buffer_position()?;
+        self.buffered_entries += frame_entries(&self.serializer, self.checksum, &mut self.buffer, base, entries)?.len();
+        if self.buffer.len() > self.buffer_capacity {
+            self.flush()?;
         }
+        Ok(())
+    }
+
+    /// Write all buffered entries to the file with a single write operation.
+    ///
+    /// If the write fails, the buffered entries are discarded and none of them
+    /// was stored. If only syncing them fails, they were written and
+    /// [`NotSynced`](JournalError::NotSynced) is returned.
+    pub fn flush(&mut self) -> Result<(), JournalError<S::Error>> {
+        self.flush_buffer()
+    }
+
+    /// Flush the buffered entries and close the writer.
+    ///
+    /// Dropping the writer flushes as well, but errors are lost then.
+    pub fn finish(mut self) -> Result<(), JournalError<S::Error>> {
+        self.flush()
+    }
 
+    /// Flush everything stored so far to stable storage, regardless of the durability policy.
+    pub fn sync(&mut self) -> Result<(), JournalError<S::Error>> {
+        self.flush()?;
+        self.sync_tracker.sync(self.file_handle.as_mut())
+            .map_err(JournalError::NotSynced)
+    }
+
+    /// Start a transaction. Its entries are written when it is committed and
+    /// discarded if it is dropped before.
+    pub fn begin_transaction(&mut self) -> Result<Transaction<'_, 'a, T, S, F>, JournalError<S::Error>> {
+        let entries = self.transaction_buffer()?;
+        Ok(Transaction {
+            writer: self,
+            entries,
+        })
+    }
+
+    /// Write buffered entries and all of `entries` with a single write operation.
+    ///
+    /// Returns the offsets of the new entries and the range of bytes that was written.
+    pub(crate) fn append_entries<I>(&mut self, entries: I) -> Result<Appended, JournalError<S::Error>> 
+    where I: Iterator<Item=T> {
+        self.ensure_header()?;
+        let base = self.buffer_position()?;
+        let starts = frame_entries(&self.serializer, self.checksum, &mut self.buffer, base, entries)?;
+        self.write_buffered(starts)
+    }
+
+    /// An empty transaction whose entries are appended after the buffered ones.
+    pub(crate) fn transaction_buffer(&mut self) -> Result<TransactionBuffer, JournalError<S::Error>> {
+        self.ensure_header()?;
+        let base = self.buffer_position()?;
+        let base = EntryPosition { offset: base.offset + self.buffer.len() as u64 + frame::BEGIN_MARKER_SIZE, ..base };
+        Ok(TransactionBuffer { buffer: Vec::new(), starts: Vec::new(), base })
+    }
+
+    /// Like [`append_entries`](JournalWriter::append_entries), but for a committed transaction.
+    pub(crate) fn append_transaction(&mut self, transaction: TransactionBuffer) -> Result<Appended, JournalError<S::Error>> {
+        self.ensure_header()?;
+        let starts = self.buffer_transaction(transaction)?;
+        self.write_buffered(starts)
+    }
+
+    /// Append the frames of a committed transaction to the buffer, enclosed in
+    /// transaction markers. Returns where its entries start in the buffer.
+    fn buffer_transaction(&mut self, transaction: TransactionBuffer) -> Result<Vec<u64>, JournalError<S::Error>> {
+        if transaction.starts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = u32::try_from(transaction.starts.len())
+            .map_err(|_| JournalError::IOError(std::io::Error::new(ErrorKind::InvalidInput, "Transaction has too many entries")))?;
+
+        frame::write_marker(&mut self.buffer, frame::Marker::Begin { count }, self.checksum);
+        let base = self.buffer.len() as u64;
+        self.buffer.extend_from_slice(&transaction.buffer);
+        frame::write_marker(&mut self.buffer, frame::Marker::Commit, self.checksum);
+        self.buffered_entries += transaction.starts.len();
+
+        Ok(transaction.starts.into_iter()
+            .map(|start| base + start)
+            .collect())
+    }
+
+    /// Write the buffer and turn the `starts` of frames in the buffer into file offsets.
+    fn write_buffered(&mut self, starts: Vec<u64>) -> Result<Appended, JournalError<S::Error>> {
+        let end = self.buffer.len() as u64;
+        let base = self.write_buffer()?;
+        let offsets = starts.into_iter()
+            .map(|start| base + start)
+            .collect();
+        Ok(Appended {
+            offsets,
+            written: base..base + end,
+        })
+    }
+
+    /// Continue after a failed write of another writer for the same file,
+    /// see [`torn_offset`](JournalWriter::torn_offset).
+    pub(crate) fn with_torn_offset(mut self, torn_offset: Option<u64>) -> Self {
+        self.torn_offset = torn_offset;
+        self
+    }
+
+    /// Where a failed write started, if its partial frames are still in the file.
+    pub(crate) fn torn_offset(&self) -> Option<u64> {
+        self.torn_offset
+    }
+
+    /// Write the file header if the file is still empty.
+    fn ensure_header(&mut self) -> Result<(), JournalError<S::Error>> {
+        if self.header.is_none() {
+            let header = FileHeader::read_or_write(self.file_handle.as_mut(),
+                FileHeader::for_serializer::<T, S>(self.checksum).with_fingerprint(self.fingerprint.clone()))?;
+            self.checksum = header.checksum;
+            self.header = Some(header);
+        }
+        Ok(())
+    }
+
+    /// The position the buffer is going to be written at, once the header is known.
+    fn buffer_position(&mut self) -> Result<EntryPosition, JournalError<S::Error>> {
+        let offset = self.buffer_offset()?;
+        Ok(self.header.as_ref().unwrap().position(offset))
+    }
+}
+
+impl<'a, T, S, F> JournalWriter<'a, T, S, F>
+where F: Storage {
+    /// The offset the buffer is going to be written at.
+    fn buffer_offset<E>(&mut self) -> Result<u64, JournalError<E>> {
+        match self.torn_offset {
+            Some(offset) => Ok(offset),
+            None => self.file_handle.as_mut().seek(SeekFrom::End(0))
+                .map_err(JournalError::IOError),
+        }
+    }
+
+    /// Write the buffered entries and sync them if the durability policy asks for it.
+    fn flush_buffer<E>(&mut self) -> Result<(), JournalError<E>> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let bytes = self.buffer.len() as u64;
+        let entries = self.buffered_entries;
+        self.write_buffer()?;
+        self.sync_tracker.written(self.file_handle.as_mut(), entries, bytes)
+            .map_err(JournalError::NotSynced)
+    }
+
+    /// Append the buffered frames to the end of the file and return the offset they were written at.
+    ///
+    /// The buffer is emptied either way. A failed write might have been
+    /// partial, its frames are truncated again so none of the entries is stored.
+    fn write_buffer<E>(&mut self) -> Result<u64, JournalError<E>> {
+        let result = self.write_all_buffered();
+        self.buffer.clear();
+        self.buffered_entries = 0;
+        result
+    }
+
+    /// Write the whole buffer at the end of the file, after removing the frames of a failed write.
+    fn write_all_buffered<E>(&mut self) -> Result<u64, JournalError<E>> {
+        let file_handle = self.file_handle.as_mut();
+        if let Some(offset) = self.torn_offset {
+            file_handle.truncate(offset)
+                .map_err(JournalError::IOError)?;
+            self.torn_offset = None;
+        }
+        let start = file_handle.seek(SeekFrom::End(0))
+            .map_err(JournalError::IOError)?;
+        if let Err(err) = file_handle.write_all(&self.buffer) {
+            // if this fails as well, try again before the next write
+            self.torn_offset = Some(start);
+            if file_handle.truncate(start).is_ok() {
+                self.torn_offset = None;
+            }
+            return Err(JournalError::IOError(err));
+        }
+        Ok(start)
+    }
+}
+
+/// Append all entries to `buffer`, which is written to the file at position
+/// `base`, and return where their frames start in the buffer.
+///
+/// If one of them can't be serialized, none of them is added.
+fn frame_entries<T, S, I>(serializer: &S, checksum: Checksum, buffer: &mut Vec<u8>, base: EntryPosition, entries: I) -> Result<Vec<u64>, JournalError<S::Error>>
+where S: JournalSerialize<T>, I: Iterator<Item=T> {
+    let buffered = buffer.len();
+    let mut starts = Vec::new();
+    for entry in entries {
+        starts.push(buffer.len() as u64);
+        if let Err(err) = frame_entry(serializer, checksum, buffer, base, entry) {
+            buffer.truncate(buffered);
+            return Err(err);
+        }
+    }
+    Ok(starts)
+}
+
+/// Serialize a single entry and append it to `buffer` as length prefixed frame.
+fn frame_entry<T, S>(serializer: &S, checksum: Checksum, buffer: &mut Vec<u8>, base: EntryPosition, entry: T) -> Result<(), JournalError<S::Error>>
+where S: JournalSerialize<T> {
+    let start = frame::begin_frame(buffer);
+    let position = EntryPosition { offset: base.offset + start as u64, ..base };
+    serializer.serialize_at(entry, position, buffer)
+        .map_err(JournalError::SerializationError)?;
+    frame::finish_frame(buffer, start, checksum)
+        .map_err(JournalError::IOError)
+}
+
+/// Where entries were appended to the file.
+#[derive(Debug)]
+pub(crate) struct Appended {
+    /// The offsets of the new entries
+    pub(crate) offsets: Vec<u64>,
+    /// The range of bytes that was written
+    pub(crate) written: Range<u64>,
+}
+
+/// The framed entries of a transaction that wasn't committed yet.
+#[derive(Debug)]
+pub(crate) struct TransactionBuffer {
+    buffer: Vec<u8>,
+    starts: Vec<u64>,
+    /// The position the buffer is written at once the transaction is committed
+    base: EntryPosition,
+}
+
+impl TransactionBuffer {
+    pub(crate) fn push<T, S, I>(&mut self, serializer: &S, checksum: Checksum, entries: I) -> Result<(), JournalError<S::Error>>
+    where S: JournalSerialize<T>, I: Iterator<Item=T> {
+        let starts = frame_entries(serializer, checksum, &mut self.buffer, self.base, entries)?;
+        self.starts.extend(starts);
+        Ok(())
+    }
+}
+
+/// A group of entries that is written completely or not at all.
+///
+/// Readers only return the entries once the whole transaction was written,
+/// even if the process crashed in between. Dropping a transaction without
+/// calling [`commit`](Transaction::commit) discards its entries.
+#[derive(Debug)]
+pub struct Transaction<'w, 'a, T, S, F = File>
+where F: Storage {
+    writer: &'w mut JournalWriter<'a, T, S, F>,
+    entries: TransactionBuffer,
+}
+
+impl<'w, 'a, T, S, F> Transaction<'w, 'a, T, S, F>
+where S: JournalSerialize<T> + Debug, T: Debug, F: Storage {
+    pub fn store_entry(&mut self, entry: T) -> Result<(), JournalError<S::Error>> {
+        self.store_entries(std::iter::once(entry))
+    }
+
+    /// Add entries to the transaction. If one of them can't be serialized,
+    /// none of them is added, but the transaction can still be committed.
+    pub fn store_entries<I>(&mut self, entries: I) -> Result<(), JournalError<S::Error>>
+    where I: Iterator<Item=T> {
+        self.entries.push(&self.writer.serializer, self.writer.checksum, entries)
+    }
+
+    /// Write all entries of the transaction to the journal.
+    ///
+    /// Like [`store_entries`](JournalWriter::store_entries), the entries stay
+    /// in the buffer of the writer if it has room for them.
+    pub fn commit(self) -> Result<(), JournalError<S::Error>> {
+        self.writer.buffer_transaction(self.entries)?;
+        if self.writer.buffer.len() > self.writer.buffer_capacity {
+            self.writer.flush()?;
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl<'a, T, S, F> Drop for JournalWriter<'a, T, S, F>
+where F: Storage {
+    /// The last resort to write buffered entries, use [`finish`](JournalWriter::finish)
+    /// to see if that failed.
+    fn drop(&mut self) {
+        let _ = self.flush_buffer::<()>();
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read, SeekFrom};
+use std::fs::File;
+use std::marker::PhantomData;
+
+use crate::*;
+
+/// The size of the chunks a file is searched backwards in for its last line break.
+const CHUNK_SIZE: u64 = 4096;
+
+fn dirty<E>() -> JournalError<E> {
+    JournalError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Journal file is dirty"))
+}
+
+/// Appends entries to a [JSON Lines](https://jsonlines.org) file, requires the `json` feature.
+///
+/// Every entry is a single line of JSON. Unlike a
+/// [`JsonJournalWriter`](crate::JsonJournalWriter), there is no header and
+/// there are no frames, so `jq`, `tail -f` and other text tools can read the
+/// file. There are no checksums, transactions or index either, a line is only
+/// known to be complete by its line break. Read the file with a
+/// [`JsonLinesReader`](JsonLinesReader).
+#[derive(Debug)]
+pub struct JsonLinesWriter<'a, T, F = File>
+where F: Storage {
+    file_handle: OwnedOrRef<'a, F>,
+    /// Entries are serialized in this buffer before they are written to the file
+    buffer: Vec<u8>,
+    /// Where a failed write started, if its partial lines couldn't be removed yet
+    torn_offset: Option<u64>,
+    type_phantom: PhantomData<*const T>,
+}
+
+impl<'a, T, F> JsonLinesWriter<'a, T, F>
+where T: serde::Serialize, F: Storage {
+    /// Append entries to the lines in `file_handle`.
+    ///
+    /// Fails if the last line of the file was only partially written, new
+    /// entries would be appended to it. Use [`recover`](JsonLinesWriter::recover)
+    /// to remove it.
+    pub fn new<FILE>(file_handle: FILE) -> Result<Self, JournalError<serde_json::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        let mut file_handle = file_handle.into();
+        let length = file_handle.as_mut().seek(SeekFrom::End(0))
+            .map_err(JournalError::IOError)?;
+        if last_line_end(file_handle.as_mut(), length)? != length {
+            return Err(dirty());
+        }
+        Ok(Self::from_handle(file_handle))
+    }
+
+    /// Like [`new`](JsonLinesWriter::new), but a partially written last line
+    /// that was left behind by a crash is truncated first.
+    pub fn recover<FILE>(file_handle: FILE) -> Result<(Self, Option<TruncatedTail>), JournalError<serde_json::Error>>
+    where FILE: Into<OwnedOrRef<'a, F>> + 'a {
+        let mut file_handle = file_handle.into();
+        let file = file_handle.as_mut();
+        let length = file.seek(SeekFrom::End(0))
+            .map_err(JournalError::IOError)?;
+        let offset = last_line_end(file, length)?;
+        let tail = if offset == length {
+            None
+        } else {
+            file.truncate(offset)
+                .and_then(|_| file.sync())
+                .map_err(JournalError::IOError)?;
+            Some(TruncatedTail { offset, dropped_bytes: length - offset })
+        };
+        Ok((Self::from_handle(file_handle), tail))
+    }
+
+    fn from_handle(file_handle: OwnedOrRef<'a, F>) -> Self {
+        Self {
+            file_handle,
+            buffer: Vec::new(),
+            torn_offset: None,
+            type_phantom: PhantomData,
+        }
+    }
+
+    pub fn store_entry(&mut self, entry: T) -> Result<(), JournalError<serde_json::Error>> {
+        self.store_entries(std::iter::once(entry))
+    }
+
+    /// Store all entries, they are written together with a single write operation.
+    ///
+    /// If one of them can't be serialized, none of them is written.
+    pub fn store_entries<I>(&mut self, entries: I) -> Result<(), JournalError<serde_json::Error>>
+    where I: Iterator<Item=T> {
+        self.buffer.clear();
+        for entry in entries {
+            // the compact format escapes line breaks in strings
+            serde_json::to_writer(&mut self.buffer, &entry)
+                .map_err(JournalError::SerializationError)?;
+            self.buffer.push(b'\n');
+        }
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let file_handle = self.file_handle.as_mut();
+        if let Some(offset) = self.torn_offset {
+            file_handle.truncate(offset)
+                .map_err(JournalError::IOError)?;
+            self.torn_offset = None;
+        }
+        let start = file_handle.seek(SeekFrom::End(0))
+            .map_err(JournalError::IOError)?;
+        if let Err(err) = file_handle.write_all(&self.buffer).and_then(|()| file_handle.flush()) {
+            // the next write must not continue a partial line
+            self.torn_offset = Some(start);
+            if file_handle.truncate(start).is_ok() {
+                self.torn_offset = None;
+            }
+            return Err(JournalError::IOError(err));
+        }
+        Ok(())
+    }
+
+    /// Flush everything stored so far to stable storage.
+    pub fn sync(&mut self) -> Result<(), JournalError<serde_json::Error>> {
+        self.file_handle.as_mut().sync()
+            .map_err(JournalError::NotSynced)
+    }
+}
+
+/// The offset after the last line break in the first `length` bytes of `file`.
+fn last_line_end<F, E>(file: &mut F, length: u64) -> Result<u64, JournalError<E>>
+where F: Storage {
+    let mut chunk = Vec::new();
+    let mut end = length;
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE);
+        chunk.resize((end - start) as usize, 0);
+        file.seek(SeekFrom::Start(start))
+            .and_then(|_| file.read_exact(&mut chunk))
+            .map_err(JournalError::IOError)?;
+        if let Some(position) = chunk.iter().rposition(|&byte| byte == b'\n') {
+            return Ok(start + position as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Reads the entries of a [JSON Lines](https://jsonlines.org) file, requires the `json` feature.
+///
+/// A line that isn't valid JSON for `T` is returned as
+/// [`SerializationError`](JournalError::SerializationError) and reading
+/// continues with the next one. A last line without its line break was only
+/// partially written, it is reported like the dirty tail of a
+/// [`LegacyJournalReader`](crate::LegacyJournalReader). Empty lines are skipped.
+#[derive(Debug)]
+pub struct JsonLinesReader<T, R> {
+    reader: BufReader<R>,
+    line: Vec<u8>,
+    /// Set after a dirty tail or a failed read, nothing can be read after it
+    failed: bool,
+    type_phantom: PhantomData<*const T>,
+}
+
+impl<T, R> JsonLinesReader<T, R>
+where T: for<'de> serde::Deserialize<'de>, R: Read {
+    /// Read the lines of `reader` from its current position.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            line: Vec::new(),
+            failed: false,
+            type_phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, R> Iterator for JsonLinesReader<T, R>
+where T: for<'de> serde::Deserialize<'de>, R: Read {
+    type Item = Result<T, JournalError<serde_json::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.failed {
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {},
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(JournalError::IOError(err)));
+                }
+            }
+            if self.line.last() != Some(&b'\n') {
+                self.failed = true;
+                return Some(Err(dirty()));
+            }
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            return Some(serde_json::from_slice(&self.line)
+                .map_err(JournalError::SerializationError));
+        }
+        None
+    }
+}
@@ -0,0 +1,71 @@
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::marker::PhantomData;
+use std::fmt::Debug;
+
+use crate::*;
+
+/// Reads journals written before entries were framed, which are a plain
+/// sequence of serialized entries without a header.
+///
+/// Newer readers reject these files with
+/// [`HeaderError::NotAJournal`](crate::HeaderError::NotAJournal). To migrate
+/// one, read its entries with this reader and store them with a
+/// [`JournalWriter`](crate::journal_writer::JournalWriter) in a new file.
+#[derive(Debug)]
+pub struct LegacyJournalReader<T, D, R> {
+    reader: BufReader<R>,
+    deserializer: D,
+    /// Set after the first error, the position of the next entry is unknown then
+    failed: bool,
+    type_phantom: PhantomData<*const T>,
+}
+
+impl<T, R> LegacyJournalReader<T, BincodeDeserializer, R>
+where T: for<'de> serde::Deserialize<'de>, R: Read {
+    /// Read a legacy journal written with the default serializer.
+    pub fn new(reader: R) -> Self {
+        Self::with_deserializer(reader, BincodeDeserializer)
+    }
+}
+
+impl<T, D, R> LegacyJournalReader<T, D, R>
+where D: JournalDeserialize<T>, R: Read {
+    /// Like [`new`](LegacyJournalReader::new), but you can provide the
+    /// deserializer the journal was written with.
+    pub fn with_deserializer(reader: R, deserializer: D) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            deserializer,
+            failed: false,
+            type_phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, D, R> Iterator for LegacyJournalReader<T, D, R>
+where D: JournalDeserialize<T>, R: Read {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => {},
+            Err(err) => {
+                self.failed = true;
+                return Some(Err(JournalError::IOError(err)));
+            }
+        }
+
+        let result = match self.deserializer.deserialize(&mut self.reader) {
+            Ok(Some(value)) => return Some(Ok(value)),
+            // there were bytes left, but not a whole entry, so the last write didn't finish
+            Ok(None) => Err(JournalError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Journal file is dirty"))),
+            Err(err) => Err(JournalError::SerializationError(err)),
+        };
+        self.failed = true;
+        Some(result)
+    }
+}
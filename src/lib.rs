@@ -1,36 +1,146 @@
-use std::io::{Read, Seek, SeekFrom};
 use std::ops::{DerefMut, Deref};
+use std::fs::File;
 
 
 mod counting_io;
+mod frame;
+pub use frame::Checksum;
+
+mod header;
+pub use header::{EntryPosition, HeaderError, Layers, TypeFingerprint};
+
+mod recovery;
+pub use recovery::TruncatedTail;
 use counting_io::*;
 
 mod owned_or_ref;
 pub use owned_or_ref::*;
 
+mod storage;
+pub use storage::Storage;
+
+mod durability;
+pub use durability::Durability;
+use durability::SyncTracker;
+
+mod retention;
+pub use retention::Retention;
+
+mod compaction;
+pub use compaction::{compact, compact_with_deserializer, compact_with_index_file, CompactionKey};
+
+mod journal_index;
+
+#[cfg(feature = "mmap")]
+mod mmap;
+
 pub mod indexed_journal;
 use indexed_journal::*;
-pub type SimpleIndexedJournal<'a, T> = IndexedJournal<'a, T, BincodeSerializer, BincodeDeserializer>;
+pub type SimpleIndexedJournal<'a, T, F = File> = IndexedJournal<'a, T, BincodeSerializer, BincodeDeserializer, F>;
 
 
 pub mod journal_writer;
 use journal_writer::*;
-pub type SimpleJournalWriter<'a, T> = JournalWriter<'a, T, BincodeSerializer>;
+pub type SimpleJournalWriter<'a, T, F = File> = JournalWriter<'a, T, BincodeSerializer, F>;
 
 pub mod journal_reader;
 use journal_reader::*;
-pub type SimpleJournalReader<'a, T> = JournalReader<'a, T, BincodeDeserializer>;
+pub type SimpleJournalReader<'a, T, F = File> = JournalReader<'a, T, BincodeDeserializer, F>;
+
+// The other built-in formats, these are created with `with_serializer` or `with_deserializer`
+#[cfg(feature = "json")]
+pub type JsonJournalWriter<'a, T, F = File> = JournalWriter<'a, T, JsonSerializer, F>;
+#[cfg(feature = "json")]
+pub type JsonJournalReader<'a, T, F = File> = JournalReader<'a, T, JsonDeserializer, F>;
+#[cfg(feature = "json")]
+pub type JsonIndexedJournal<'a, T, F = File> = IndexedJournal<'a, T, JsonSerializer, JsonDeserializer, F>;
+#[cfg(feature = "json")]
+mod json_lines;
+#[cfg(feature = "json")]
+pub use json_lines::{JsonLinesReader, JsonLinesWriter};
+
+#[cfg(feature = "msgpack")]
+pub type MsgPackJournalWriter<'a, T, F = File> = JournalWriter<'a, T, MsgPackSerializer, F>;
+#[cfg(feature = "msgpack")]
+pub type MsgPackJournalReader<'a, T, F = File> = JournalReader<'a, T, MsgPackDeserializer, F>;
+#[cfg(feature = "msgpack")]
+pub type MsgPackIndexedJournal<'a, T, F = File> = IndexedJournal<'a, T, MsgPackSerializer, MsgPackDeserializer, F>;
+
+#[cfg(feature = "cbor")]
+pub type CborJournalWriter<'a, T, F = File> = JournalWriter<'a, T, CborSerializer, F>;
+#[cfg(feature = "cbor")]
+pub type CborJournalReader<'a, T, F = File> = JournalReader<'a, T, CborDeserializer, F>;
+#[cfg(feature = "cbor")]
+pub type CborIndexedJournal<'a, T, F = File> = IndexedJournal<'a, T, CborSerializer, CborDeserializer, F>;
+
+#[cfg(feature = "postcard")]
+pub type PostcardJournalWriter<'a, T, F = File> = JournalWriter<'a, T, PostcardSerializer, F>;
+#[cfg(feature = "postcard")]
+pub type PostcardJournalReader<'a, T, F = File> = JournalReader<'a, T, PostcardDeserializer, F>;
+#[cfg(feature = "postcard")]
+pub type PostcardIndexedJournal<'a, T, F = File> = IndexedJournal<'a, T, PostcardSerializer, PostcardDeserializer, F>;
+
+pub mod codec;
+pub use codec::{Codec, Compressed, CompressionError};
+
+#[cfg(feature = "encryption")]
+pub mod encryption;
+#[cfg(feature = "encryption")]
+pub use encryption::{Encrypted, EncryptionError};
+
+pub mod versioning;
+pub use versioning::{migrate_to_versioned, Upcast, Upcaster, VersionedSerializer, VersionedDeserializer, VersionedError};
+
+pub mod journal_slice;
+use journal_slice::*;
+pub type SimpleJournalSlice<'data, T> = JournalSlice<'data, T, BincodeDeserializer>;
+
+mod legacy;
+pub use legacy::LegacyJournalReader;
+
+pub mod segmented_journal;
+use segmented_journal::*;
+pub type SimpleSegmentedJournal<T> = SegmentedJournal<T, BincodeSerializer, BincodeDeserializer>;
+
+pub mod snapshot;
+use snapshot::*;
+pub type SimpleSnapshots<T> = Snapshots<T, BincodeSerializer, BincodeDeserializer>;
 
 #[derive(Debug)]
 pub enum JournalError<SE> {
     IndexOutOfBounds,
     IOError(std::io::Error),
     SerializationError(SE),
+    /// The checksum of the entry at the given byte offset doesn't match its content.
+    Corrupted { offset: u64 },
+    /// The file doesn't start with a header this reader or writer accepts.
+    InvalidHeader(HeaderError),
+    /// The entry was removed by the retention policy, `first_index` is the oldest entry that is left.
+    EntryRemoved { first_index: usize },
+    /// The entries with these indices were written, but syncing them or
+    /// adding them to the index failed. Storing them again would duplicate them.
+    SyncFailed { indices: std::ops::Range<usize>, error: std::io::Error },
+    /// The entries were written, but syncing them failed.
+    /// Storing them again would duplicate them.
+    NotSynced(std::io::Error),
+}
+
+/// Deserialize the payload of the frame at `position`.
+pub(crate) fn decode_entry<T, D>(deserializer: &D, position: EntryPosition, payload: &[u8]) -> Result<T, JournalError<D::Error>>
+where D: journal_reader::JournalDeserialize<T> {
+    let mut reader = payload;
+    match deserializer.deserialize_at(position, &mut reader) {
+        Ok(Some(value)) => Ok(value),
+        // the frame is complete, so the payload itself must be broken
+        Ok(None) => Err(JournalError::Corrupted { offset: position.offset }),
+        Err(err) => Err(JournalError::SerializationError(err)),
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct JournalEntry<T> {
     pub value: T,
+    #[allow(dead_code)]
     pub offset: u64,
 }
 
@@ -56,13 +166,1187 @@ impl<T> DerefMut for JournalEntry<T> {
 
 #[cfg(test)]
 mod tests {
-//    use super::*;
-//    use std::path::Path;
-//    use std::fs::File;
-//
+    use super::*;
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write, Cursor};
+
+    fn temp_file(name: &str) -> File {
+        let path = std::env::temp_dir()
+            .join(format!("journal_file_{}_{}", std::process::id(), name));
+        OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap()
+    }
+
     #[test]
     fn test_example_01() {
     }
+
+    #[test]
+    fn test_frames_are_indexed_without_deserializing() {
+        let mut file = temp_file("frames");
+        SimpleJournalWriter::new(&mut file).unwrap()
+            .store_entries(vec![1u64, 2, 3].into_iter())
+            .unwrap();
+
+        // a reader of an unrelated type can still step over the records
+        let mut journal: SimpleIndexedJournal<String> = SimpleIndexedJournal::new(&mut file).unwrap();
+        assert!(journal.load_entry(2).is_err());
+        assert!(matches!(journal.load_entry(3), Err(JournalError::IndexOutOfBounds)));
+
+        let mut journal: SimpleIndexedJournal<u64> = SimpleIndexedJournal::new(&mut file).unwrap();
+        assert_eq!(journal.load_entry(2).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_torn_frame_is_dirty() {
+        let mut file = temp_file("torn");
+        SimpleJournalWriter::new(&mut file).unwrap().store_entry("complete").unwrap();
+        file.write_all(&[100, 0, 0, 0, 1, 2]).unwrap();
+
+        let values = SimpleJournalReader::<String>::new(&mut file).unwrap().iter().collect::<Vec<_>>();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].as_ref().unwrap(), "complete");
+        match &values[1] {
+            Err(JournalError::IOError(err)) => assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(SimpleIndexedJournal::<String>::new(&mut file).is_err());
+    }
+
+    #[test]
+    fn test_legacy_journal_can_be_migrated() {
+        use bincode::Options;
+
+        // journals used to be plain bincode values without header and frames
+        let mut legacy = Vec::new();
+        for value in ["first", "second", "third"] {
+            bincode::options().with_varint_encoding().serialize_into(&mut legacy, value).unwrap();
+        }
+        assert!(matches!(
+            SimpleJournalReader::<String, _>::new(Cursor::new(legacy.clone())),
+            Err(JournalError::InvalidHeader(HeaderError::NotAJournal))));
+
+        let mut buffer = Cursor::new(Vec::new());
+        let entries = LegacyJournalReader::<String, _, _>::new(&legacy[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        SimpleJournalWriter::new(&mut buffer).unwrap()
+            .store_entries(entries.into_iter())
+            .unwrap();
+        let values = SimpleJournalReader::<String, _>::new(&mut buffer).unwrap()
+            .iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(values, vec!["first", "second", "third"]);
+
+        // an unfinished last entry is reported instead of being dropped
+        legacy.pop();
+        let values = LegacyJournalReader::<String, _, _>::new(&legacy[..]).collect::<Vec<_>>();
+        assert_eq!(values.len(), 3);
+        assert!(matches!(values[2], Err(JournalError::IOError(ref err)) if err.kind() == std::io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_bit_flip_is_reported_as_corruption() {
+        use std::io::{Seek, SeekFrom};
+
+        let mut file = temp_file("bit_flip");
+        SimpleJournalWriter::new(&mut file).unwrap()
+            .store_entries(vec!["first", "second", "third"].into_iter())
+            .unwrap();
+        // flip a bit in the payload of the second entry
+        let data_start = header::FileHeader::new("bincode", Checksum::Crc32c).size();
+        let second = data_start + 8 + 6;
+        file.seek(SeekFrom::Start(second + 9)).unwrap();
+        file.write_all(b"S").unwrap();
+
+        let values = SimpleJournalReader::<String>::new(&mut file).unwrap().iter().collect::<Vec<_>>();
+        assert!(matches!(values[1], Err(JournalError::Corrupted { offset }) if offset == second));
+
+        let mut journal: SimpleIndexedJournal<String> = SimpleIndexedJournal::new(&mut file).unwrap();
+        assert_eq!(journal.load_entry(2).unwrap(), "third");
+        assert!(matches!(journal.load_entry(1), Err(JournalError::Corrupted { offset }) if offset == second));
+        let values = journal.iter_from(1).unwrap().collect::<Vec<_>>();
+        assert!(matches!(values[0], Err(JournalError::Corrupted { offset }) if offset == second));
+    }
+
+    #[test]
+    fn test_header_is_verified() {
+        let mut file = temp_file("header");
+        file.write_all(b"this is not a journal").unwrap();
+        assert!(matches!(SimpleJournalReader::<String>::new(&mut file),
+            Err(JournalError::InvalidHeader(HeaderError::NotAJournal))));
+        assert!(matches!(SimpleIndexedJournal::<String>::new(&mut file),
+            Err(JournalError::InvalidHeader(HeaderError::NotAJournal))));
+        assert!(matches!(SimpleJournalWriter::<String>::new(&mut file),
+            Err(JournalError::InvalidHeader(HeaderError::NotAJournal))));
+
+        #[derive(Debug, Clone, Copy)]
+        struct OtherSerializer;
+        impl journal_writer::JournalSerialize<String> for OtherSerializer {
+            type Error = bincode::Error;
+            const FORMAT_ID: &'static str = "other";
+            fn serialize(&self, value: String, writer: &mut dyn std::io::Write) -> Result<(), Self::Error> {
+                journal_writer::BincodeSerializer.serialize(value, writer)
+            }
+        }
+
+        let mut file = temp_file("header_format");
+        JournalWriter::with_serializer(&mut file, OtherSerializer).unwrap()
+            .store_entry("entry".to_string())
+            .unwrap();
+        match SimpleJournalReader::<String>::new(&mut file) {
+            Err(JournalError::InvalidHeader(HeaderError::FormatMismatch { expected, found })) => {
+                assert_eq!(expected, "bincode");
+                assert_eq!(found, "other");
+            },
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        // empty files are valid journals
+        let mut file = temp_file("header_empty");
+        assert_eq!(SimpleJournalReader::<String>::new(&mut file).unwrap().iter().count(), 0);
+        SimpleJournalWriter::new(&mut file).unwrap().store_entry("first").unwrap();
+        let values = SimpleJournalReader::<String>::new(&mut file).unwrap().iter()
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec!["first"]);
+    }
+
+    #[test]
+    fn test_recover_truncates_torn_tail() {
+        use std::io::{Seek, SeekFrom};
+
+        let mut file = temp_file("recover");
+        SimpleJournalWriter::new(&mut file).unwrap()
+            .store_entries(vec![1u64, 2].into_iter())
+            .unwrap();
+        let length = file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(&[20, 0, 0, 0, 1, 2, 3]).unwrap();
+
+        let (mut journal, tail) = SimpleIndexedJournal::<u64>::recover(&mut file).unwrap();
+        assert_eq!(tail, Some(TruncatedTail { offset: length, dropped_bytes: 7 }));
+        journal.store_entry(3).unwrap();
+        let values = journal.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![1, 2, 3]);
+
+        let (_, tail) = SimpleJournalWriter::<u64>::recover(&mut file).unwrap();
+        assert_eq!(tail, None);
+
+        // a crash while creating the journal leaves only part of the header behind
+        let mut file = temp_file("recover_header");
+        file.write_all(b"JRNL").unwrap();
+        let (mut writer, tail) = SimpleJournalWriter::recover(&mut file).unwrap();
+        assert_eq!(tail, Some(TruncatedTail { offset: 0, dropped_bytes: 4 }));
+        writer.store_entry(1u64).unwrap();
+
+        // without checksums, empty entries look like a zero filled tail and are kept
+        let mut buffer = Cursor::new(Vec::new());
+        SimpleJournalWriter::new(&mut buffer).unwrap()
+            .with_checksum(Checksum::None)
+            .store_entries(vec![(), (), ()].into_iter())
+            .unwrap();
+        let (mut journal, tail) = SimpleIndexedJournal::<(), _>::recover(&mut buffer).unwrap();
+        assert_eq!(tail, None);
+        assert_eq!(journal.iter().collect::<Result<Vec<_>, _>>().unwrap(), vec![(), (), ()]);
+
+        // a corrupted length makes an entry look torn, but the entries after it are still valid
+        let mut buffer = Cursor::new(Vec::new());
+        SimpleJournalWriter::new(&mut buffer).unwrap()
+            .store_entries(vec![1u64, 2, 3, 4, 5].into_iter())
+            .unwrap();
+        let second = header::FileHeader::new("bincode", Checksum::Crc32c).size() + 8 + 1;
+        buffer.get_mut()[second as usize + 2] ^= 1;
+        let complete = buffer.get_ref().clone();
+        assert!(matches!(SimpleIndexedJournal::<u64, _>::recover(&mut buffer),
+            Err(JournalError::Corrupted { offset }) if offset == second));
+        assert_eq!(buffer.get_ref(), &complete);
+    }
+
+    #[test]
+    fn test_reverse_iteration() {
+        let mut journal = SimpleIndexedJournal::<u64, _>::new(Cursor::new(Vec::new())).unwrap();
+        assert!(journal.iter_rev().next().is_none());
+        journal.store_entries(1..=100).unwrap();
+
+        let newest = journal.iter_rev().take(3).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(newest, vec![100, 99, 98]);
+        assert_eq!(journal.iter_rev().len(), 100);
+        assert_eq!(journal.iter_rev().next_back().unwrap().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut journal = SimpleIndexedJournal::<u64, _>::new(Cursor::new(Vec::new())).unwrap();
+        journal.store_entries(0..10).unwrap();
+
+        let range = journal.range(2..5).unwrap();
+        assert_eq!(range.len(), 3);
+        assert_eq!(range.collect::<Result<Vec<_>, _>>().unwrap(), vec![2, 3, 4]);
+        assert_eq!(journal.range(8..).unwrap().collect::<Result<Vec<_>, _>>().unwrap(), vec![8, 9]);
+        assert_eq!(journal.range(..=1).unwrap().collect::<Result<Vec<_>, _>>().unwrap(), vec![0, 1]);
+        assert_eq!(journal.range(10..).unwrap().count(), 0);
+        assert!(matches!(journal.range(5..11), Err(JournalError::IndexOutOfBounds)));
+
+        // entries appended later aren't part of the range
+        let mut range = journal.range(9..).unwrap();
+        assert_eq!(range.next().unwrap().unwrap(), 9);
+        journal.store_entry(10).unwrap();
+        assert_eq!(journal.range(9..).unwrap().len(), 2);
+
+        // a corrupted entry of a transaction only fails its own index
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = SimpleJournalWriter::new(&mut buffer).unwrap();
+        writer.store_entry(0u64).unwrap();
+        let mut transaction = writer.begin_transaction().unwrap();
+        transaction.store_entries(vec![1, 2, 3].into_iter()).unwrap();
+        transaction.commit().unwrap();
+        writer.store_entry(4).unwrap();
+        drop(writer);
+        let third = header::FileHeader::new("bincode", Checksum::Crc32c).size() + 9 + frame::BEGIN_MARKER_SIZE + 9;
+        buffer.get_mut()[third as usize + 8] ^= 0xff;
+
+        let mut journal = SimpleIndexedJournal::<u64, _>::new(&mut buffer).unwrap();
+        let values = journal.range(0..5).unwrap().collect::<Vec<_>>();
+        assert_eq!(values.len(), 5);
+        assert!(matches!(values[2], Err(JournalError::Corrupted { offset }) if offset == third));
+        assert_eq!(values[3].as_ref().unwrap(), &3);
+        assert_eq!(values[4].as_ref().unwrap(), &4);
+    }
+
+    #[test]
+    fn test_sidecar_index_is_extended() {
+        let mut file = temp_file("sidecar");
+        let mut index_file = temp_file("sidecar.idx");
+        SimpleJournalWriter::new(&mut file).unwrap()
+            .store_entries(vec![1u64, 2].into_iter())
+            .unwrap();
+
+        let mut journal = SimpleIndexedJournal::<u64>::new_with_index_file(&mut file, &mut index_file).unwrap();
+        assert_eq!(journal.load_entry(1).unwrap(), 2);
+        drop(journal);
+        let index_length = index_file.metadata().unwrap().len();
+
+        SimpleJournalWriter::new(&mut file).unwrap().store_entry(3u64).unwrap();
+        let mut journal = SimpleIndexedJournal::<u64>::new_with_index_file(&mut file, &mut index_file).unwrap();
+        assert_eq!(journal.load_entry(2).unwrap(), 3);
+        drop(journal);
+        assert_eq!(index_file.metadata().unwrap().len(), index_length + 8);
+
+        // the sidecar doesn't match a journal that was replaced
+        let mut other = temp_file("sidecar_other");
+        SimpleJournalWriter::new(&mut other).unwrap().store_entry(10u64).unwrap();
+        let mut journal = SimpleIndexedJournal::<u64>::new_with_index_file(&mut other, &mut index_file).unwrap();
+        assert_eq!(journal.load_entry(0).unwrap(), 10);
+        assert!(matches!(journal.load_entry(1), Err(JournalError::IndexOutOfBounds)));
+        drop(journal);
+
+        // not even if its first and last entry are at the same offsets
+        let mut other = temp_file("sidecar_same_layout");
+        SimpleJournalWriter::new(&mut other).unwrap().store_entry([4u8; 10]).unwrap();
+        SimpleJournalWriter::new(&mut other).unwrap().store_entry(7u64).unwrap();
+        SimpleIndexedJournal::<u64>::new_with_index_file(&mut file, &mut index_file).unwrap();
+        assert_eq!(other.metadata().unwrap().len(), file.metadata().unwrap().len());
+        let mut journal = SimpleIndexedJournal::<u64>::new_with_index_file(&mut other, &mut index_file).unwrap();
+        assert_eq!(journal.len(), 2);
+        assert_eq!(journal.load_entry(1).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_stored_entries_are_indexed() {
+        let mut file = temp_file("store_index");
+        let mut index_file = temp_file("store_index.idx");
+        let mut other_handle = file.try_clone().unwrap();
+        let mut journal = SimpleIndexedJournal::<u64>::new_with_index_file(&mut file, &mut index_file).unwrap();
+        assert_eq!(journal.store_entry(1).unwrap(), 0);
+        assert_eq!(journal.store_entries(vec![2, 3].into_iter()).unwrap(), 1..3);
+        assert_eq!(journal.load_entry(2).unwrap(), 3);
+
+        // entries appended by another writer are indexed as well
+        SimpleJournalWriter::new(&mut other_handle).unwrap()
+            .store_entry(4u64)
+            .unwrap();
+        assert_eq!(journal.store_entry(5).unwrap(), 4);
+        assert_eq!(journal.load_entry(3).unwrap(), 4);
+        drop(journal);
+
+        let mut journal = SimpleIndexedJournal::<u64>::new_with_index_file(&mut file, &mut index_file).unwrap();
+        let values = journal.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![1, 2, 3, 4, 5]);
+        assert_eq!(journal.load_entry(4).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_type_fingerprint_is_verified() {
+        let mut file = temp_file("fingerprint");
+        SimpleJournalWriter::new(&mut file).unwrap()
+            .with_fingerprint(TypeFingerprint::new("Event", 1)).unwrap()
+            .store_entry(42u64)
+            .unwrap();
+
+        let reader = SimpleJournalReader::<u64>::new(&mut file).unwrap()
+            .with_fingerprint(TypeFingerprint::new("Event", 1));
+        assert!(reader.is_ok());
+
+        let reader = SimpleJournalReader::<String>::new(&mut file).unwrap()
+            .with_fingerprint(TypeFingerprint::new("Name", 1));
+        match reader {
+            Err(JournalError::InvalidHeader(HeaderError::TypeMismatch { expected, found })) => {
+                assert_eq!(expected.name(), "Name");
+                assert_eq!(found, Some(TypeFingerprint::new("Event", 1)));
+            },
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        let journal = SimpleIndexedJournal::<u64>::new(&mut file).unwrap()
+            .with_fingerprint(TypeFingerprint::new("Event", 2));
+        assert!(matches!(journal, Err(JournalError::InvalidHeader(HeaderError::TypeMismatch { .. }))));
+        let writer = SimpleJournalWriter::<u64>::new(&mut file).unwrap()
+            .with_fingerprint(TypeFingerprint::new("Other", 1));
+        assert!(matches!(writer, Err(JournalError::InvalidHeader(HeaderError::TypeMismatch { .. }))));
+    }
+
+    #[test]
+    fn test_follow_waits_for_complete_entries() {
+        use std::io::{Read, Seek, SeekFrom};
+        use std::time::Duration;
+
+        let mut file = temp_file("follow");
+        let mut writer_file = file.try_clone().unwrap();
+        SimpleJournalWriter::new(&mut writer_file).unwrap()
+            .store_entries(vec![1u64, 2, 3].into_iter())
+            .unwrap();
+        // cut the last entry in half, as if it was still being written
+        let mut content = Vec::new();
+        writer_file.seek(SeekFrom::Start(0)).unwrap();
+        writer_file.read_to_end(&mut content).unwrap();
+        let cut = content.len() - 2;
+        writer_file.set_len(cut as u64).unwrap();
+
+        let mut reader = SimpleJournalReader::<u64>::new(&mut file).unwrap();
+        let mut follow = reader.follow()
+            .poll_interval(Duration::from_millis(1))
+            .timeout(Duration::from_millis(20));
+        assert_eq!(follow.next().unwrap().unwrap(), 1);
+        assert_eq!(follow.next().unwrap().unwrap(), 2);
+        assert!(follow.next().is_none());
+
+        writer_file.seek(SeekFrom::End(0)).unwrap();
+        writer_file.write_all(&content[cut..]).unwrap();
+        assert_eq!(follow.next().unwrap().unwrap(), 3);
+        assert!(follow.next().is_none());
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("journal_file_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn test_segmented_journal() {
+        let directory = temp_dir("segments");
+        let mut journal = SimpleSegmentedJournal::<u64>::open(&directory).unwrap()
+            .with_max_segment_entries(3);
+        for value in 0..7 {
+            assert_eq!(journal.store_entry(value).unwrap(), value as usize);
+        }
+        // a batch is never split between segments
+        assert_eq!(journal.store_entries(7..10).unwrap(), 7..10);
+        assert_eq!(journal.segment_count(), 3);
+        drop(journal);
+
+        let mut journal = SimpleSegmentedJournal::<u64>::open(&directory).unwrap()
+            .with_max_segment_bytes(1024);
+        assert_eq!(journal.len(), 10);
+        assert_eq!(journal.load_entry(7).unwrap(), 7);
+        assert!(matches!(journal.load_entry(10), Err(JournalError::IndexOutOfBounds)));
+        journal.store_entry(10).unwrap();
+        assert_eq!(journal.segment_count(), 3);
+
+        let values = journal.iter_from(2).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, (2..=10).collect::<Vec<_>>());
+        assert_eq!(journal.iter().count(), 11);
+
+        let values = journal.range(2..5).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![2, 3, 4]);
+        assert!(matches!(journal.range(5..12), Err(JournalError::IndexOutOfBounds)));
+        let newest = journal.iter_rev().take(4).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(newest, vec![10, 9, 8, 7]);
+
+        let mut transaction = journal.begin_transaction().unwrap();
+        transaction.store_entries(vec![11, 12].into_iter()).unwrap();
+        assert_eq!(transaction.commit().unwrap(), 11..13);
+        assert_eq!(journal.load_entry(12).unwrap(), 12);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_retention() {
+        let directory = temp_dir("retention");
+        let archive = temp_dir("retention_archive");
+        let mut journal = SimpleSegmentedJournal::<u64>::open(&directory).unwrap()
+            .with_max_segment_entries(2)
+            .with_retention(Retention::new().max_entries(3).archive_to(&archive));
+        for value in 0..7 {
+            journal.store_entry(value).unwrap();
+        }
+        // only whole segments are removed, and indices don't change
+        assert_eq!(journal.first_index(), 4);
+        assert_eq!(journal.len(), 7);
+        assert_eq!(journal.load_entry(5).unwrap(), 5);
+        assert!(matches!(journal.load_entry(1), Err(JournalError::EntryRemoved { first_index: 4 })));
+        assert!(matches!(journal.iter_from(0), Err(JournalError::EntryRemoved { first_index: 4 })));
+        assert_eq!(journal.iter().map(Result::unwrap).collect::<Vec<_>>(), vec![4, 5, 6]);
+        assert_eq!(std::fs::read_dir(&archive).unwrap().count(), 2);
+        drop(journal);
+
+        let mut journal = SimpleSegmentedJournal::<u64>::open(&directory).unwrap()
+            .with_retention(Retention::new().max_bytes(0));
+        assert_eq!(journal.enforce_retention().unwrap(), 1);
+        assert_eq!(journal.first_index(), 6);
+        assert_eq!(journal.store_entry(7).unwrap(), 7);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        // segments of another journal don't replace the archived ones
+        let other = temp_dir("retention_other");
+        let mut journal = SimpleSegmentedJournal::<u64>::open(&other).unwrap()
+            .with_max_segment_entries(2)
+            .with_retention(Retention::new().max_entries(1).archive_to(&archive));
+        journal.store_entries(0..2).unwrap();
+        journal.store_entry(2).unwrap();
+        assert_eq!(journal.first_index(), 2);
+        assert_eq!(std::fs::read_dir(&archive).unwrap().count(), 3);
+        std::fs::remove_dir_all(&other).unwrap();
+        std::fs::remove_dir_all(&archive).unwrap();
+    }
+
+    #[test]
+    fn test_compaction() {
+        // (key, value), a missing value deletes the key
+        type Update = (u32, Option<String>);
+        let directory = temp_dir("compaction");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("changes");
+        let updates: Vec<Update> = vec![
+            (1, Some("a".into())), (2, Some("b".into())), (1, Some("c".into())),
+            (3, Some("d".into())), (2, None), (4, Some("e".into())), (3, Some("f".into())),
+        ];
+        SimpleJournalWriter::new(File::create(&path).unwrap()).unwrap()
+            .store_entries(updates.into_iter()).unwrap();
+
+        let key = |update: &Update| match update.1 {
+            Some(_) => CompactionKey::Value(update.0),
+            None => CompactionKey::Tombstone(update.0),
+        };
+        // the last two entries are left alone, but still replace older values
+        assert_eq!(compact(&path, Some(5), key).unwrap(), 4);
+        let mut journal = SimpleIndexedJournal::<Update>::new(File::open(&path).unwrap()).unwrap();
+        let keys = journal.iter().map(|update| update.unwrap().0).collect::<Vec<_>>();
+        assert_eq!(keys, vec![1, 4, 3]);
+        drop(journal);
+
+        assert_eq!(compact(&path, None, key).unwrap(), 0);
+        assert!(!directory.join("changes.compact").exists());
+
+        // the sidecar index of the old journal must not be used for the compacted one
+        let index_path = directory.join("changes.idx");
+        let open = |path: &std::path::Path| OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path).unwrap();
+        let mut journal = SimpleIndexedJournal::<Update>::new_with_index_file(open(&path), open(&index_path)).unwrap();
+        journal.store_entries(vec![(5, Some("g".into())), (1, None)].into_iter()).unwrap();
+        drop(journal);
+        assert_eq!(compact_with_index_file(&path, &index_path, BincodeDeserializer, None, key).unwrap(), 2);
+        assert!(!index_path.exists());
+        let mut journal = SimpleIndexedJournal::<Update>::new_with_index_file(open(&path), open(&index_path)).unwrap();
+        let keys = journal.iter().map(|update| update.unwrap().0).collect::<Vec<_>>();
+        assert_eq!(keys, vec![4, 3, 5]);
+        assert_eq!(journal.load_entry(2).unwrap(), (5, Some("g".into())));
+        drop(journal);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_snapshots() {
+        let directory = temp_dir("snapshots");
+        let mut journal = SimpleSegmentedJournal::<u64>::open(directory.join("journal")).unwrap();
+        let snapshots = SimpleSnapshots::<u64>::open(directory.join("snapshots")).unwrap();
+        let sum = |state: &mut u64, entry: u64| *state += entry;
+        assert_eq!(snapshots.restore_segmented(&mut journal, 0, sum).unwrap(), 0);
+
+        journal.store_entries(1..=4).unwrap();
+        snapshots.save(10, 4).unwrap();
+        journal.store_entries(5..=6).unwrap();
+        snapshots.save(21, 6).unwrap();
+        journal.store_entry(7).unwrap();
+        assert_eq!(snapshots.load_latest().unwrap(), Some((21, 6)));
+        // an entry that is already part of the snapshot would be counted twice
+        assert_eq!(snapshots.restore_segmented(&mut journal, 1000, sum).unwrap(), 28);
+
+        // the newest snapshot is corrupted, the older one is used instead
+        let newest = directory.join("snapshots").join(format!("{:020}.snapshot", 6));
+        let mut bytes = std::fs::read(&newest).unwrap();
+        *bytes.last_mut().unwrap() ^= 0xff;
+        std::fs::write(&newest, bytes).unwrap();
+        assert_eq!(snapshots.load_latest().unwrap(), Some((10, 4)));
+        assert_eq!(snapshots.restore_segmented(&mut journal, 1000, sum).unwrap(), 28);
+
+        snapshots.save(28, 7).unwrap();
+        snapshots.save(28, 7).unwrap();
+        assert_eq!(std::fs::read_dir(directory.join("snapshots")).unwrap().count(), 2);
+
+        // a snapshot with a lower index is still the most recent one and kept
+        snapshots.save(3, 2).unwrap();
+        assert_eq!(std::fs::read_dir(directory.join("snapshots")).unwrap().count(), 2);
+        assert!(directory.join("snapshots").join(format!("{:020}.snapshot", 2)).exists());
+        assert!(directory.join("snapshots").join(format!("{:020}.snapshot", 7)).exists());
+
+        // the journal may use another deserializer than the snapshots
+        let mut buffer = Cursor::new(Vec::new());
+        let mut versioned = IndexedJournal::<u64, _, _, _>::with_serializer(&mut buffer,
+            VersionedSerializer::new(BincodeSerializer, 1),
+            VersionedDeserializer::new(BincodeDeserializer, 1)).unwrap();
+        versioned.store_entries(1..=7).unwrap();
+        assert_eq!(snapshots.restore(&mut versioned, 1000, sum).unwrap(), 28);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        // entries after the snapshot were removed, so the state can't be rebuilt
+        let directory = temp_dir("snapshots_retention");
+        let mut journal = SimpleSegmentedJournal::<u64>::open(directory.join("journal")).unwrap()
+            .with_max_segment_entries(2)
+            .with_retention(Retention::new().max_entries(2));
+        let snapshots = SimpleSnapshots::<u64>::open(directory.join("snapshots")).unwrap();
+        journal.store_entries(0..2).unwrap();
+        snapshots.save(0, 1).unwrap();
+        journal.store_entries(2..4).unwrap();
+        journal.store_entries(4..6).unwrap();
+        assert_eq!(journal.first_index(), 2);
+        assert!(matches!(snapshots.restore_segmented(&mut journal, 0, sum),
+            Err(JournalError::EntryRemoved { first_index: 2 })));
+        snapshots.save(1, 2).unwrap();
+        assert_eq!(snapshots.restore_segmented(&mut journal, 0, sum).unwrap(), 15);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap() {
+        let mut journal = SimpleIndexedJournal::<String>::new(temp_file("mmap")).unwrap()
+            .with_mmap().unwrap();
+        assert!(matches!(journal.load_entry(0), Err(JournalError::IndexOutOfBounds)));
+        journal.store_entry("hello".to_string()).unwrap();
+        assert_eq!(journal.load_entry(0).unwrap(), "hello");
+
+        // the file grows beyond the map
+        let long = "x".repeat(100_000);
+        journal.store_entries(vec!["world".to_string(), long.clone()].into_iter()).unwrap();
+        assert_eq!(journal.load_entry(2).unwrap(), long);
+        assert_eq!(journal.load_entry(1).unwrap(), "world");
+
+        // the file was truncated behind the journal's back
+        let file = temp_file("mmap_truncated");
+        let mut journal = SimpleIndexedJournal::<String>::new(file.try_clone().unwrap()).unwrap();
+        journal.store_entries(vec!["hello".to_string(), "world".to_string()].into_iter()).unwrap();
+        let mut journal = journal.with_mmap().unwrap();
+        file.set_len(20).unwrap();
+        assert!(matches!(journal.load_entry(1), Err(JournalError::IOError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_borrowed_entries() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = SimpleJournalWriter::new(&mut buffer).unwrap();
+        writer.store_entry("hello").unwrap();
+        let mut transaction = writer.begin_transaction().unwrap();
+        transaction.store_entries(vec!["big", "world"].into_iter()).unwrap();
+        transaction.commit().unwrap();
+        writer.store_entry("!").unwrap();
+        drop(writer);
+        let data = buffer.into_inner();
+
+        let journal = SimpleJournalSlice::<&str>::new(&data).unwrap();
+        let entries = journal.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(entries, vec!["hello", "big", "world", "!"]);
+        // the entries point into the journal data
+        assert!(data.as_ptr_range().contains(&entries[2].as_ptr()));
+
+        // a transaction without commit marker is never returned
+        let torn = &data[..data.len() - 12];
+        let entries = SimpleJournalSlice::<&str>::new(torn).unwrap().iter().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(*entries[0].as_ref().unwrap(), "hello");
+        assert!(entries[1].is_err());
+    }
+
+    #[cfg(all(feature = "zstd", feature = "lz4"))]
+    #[test]
+    fn test_compression() {
+        use crate::codec::{Lz4, Zstd};
+
+        let mut buffer = Cursor::new(Vec::new());
+        let line = "the same event, over and over again".repeat(20);
+        let mut journal = IndexedJournal::<String, _, _, _>::with_serializer(&mut buffer,
+            Compressed::new(BincodeSerializer, Zstd::default()),
+            Compressed::new(BincodeDeserializer, Zstd::default())).unwrap();
+        journal.store_entries(vec![line.clone(), "short".to_string()].into_iter()).unwrap();
+        assert_eq!(journal.load_entry(0).unwrap(), line);
+        assert_eq!(journal.load_entry(1).unwrap(), "short");
+        drop(journal);
+        assert!(buffer.get_ref().len() < line.len() / 4);
+
+        let reader = JournalReader::<String, _, _>::with_deserializer(&mut buffer, Compressed::new(BincodeDeserializer, Zstd::default()));
+        assert_eq!(reader.unwrap().iter().count(), 2);
+        // the codec is part of the format
+        let reader = JournalReader::<String, _, _>::with_deserializer(&mut buffer, Compressed::new(BincodeDeserializer, Lz4));
+        assert!(matches!(reader, Err(JournalError::InvalidHeader(HeaderError::CodecMismatch { .. }))));
+        assert!(matches!(SimpleJournalReader::<String, _>::new(&mut buffer),
+            Err(JournalError::InvalidHeader(HeaderError::CodecMismatch { expected: None, .. }))));
+
+        // the order of the wrappers is part of the format as well
+        let mut buffer = Cursor::new(Vec::new());
+        JournalWriter::<String, _, _>::with_serializer(&mut buffer, Compressed::new(VersionedSerializer::new(BincodeSerializer, 1), Lz4))
+            .unwrap().store_entry(line.clone()).unwrap();
+        let reader = JournalReader::<String, _, _>::with_deserializer(&mut buffer,
+            VersionedDeserializer::new(Compressed::new(BincodeDeserializer, Lz4), 1));
+        assert!(matches!(reader, Err(JournalError::InvalidHeader(HeaderError::LayerOrderMismatch { .. }))));
+        let reader = JournalReader::<String, _, _>::with_deserializer(&mut buffer,
+            Compressed::new(VersionedDeserializer::new(BincodeDeserializer, 1), Lz4));
+        assert_eq!(reader.unwrap().iter().next().unwrap().unwrap(), line);
+
+        // a corrupted size prefix must not allocate gigabytes
+        let zeros = vec![0; 100_000];
+        assert_eq!(Lz4.decompress(&Lz4.compress(&zeros).unwrap()).unwrap(), zeros);
+        let err = Lz4.decompress(&[0xff, 0xff, 0xff, 0x7f, 0x10, 0x00]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encryption() {
+        let key = [7u8; 32];
+        let mut buffer = Cursor::new(Vec::new());
+        let mut journal = IndexedJournal::<String, _, _, _>::with_serializer(&mut buffer,
+            Encrypted::new(BincodeSerializer, key), Encrypted::new(BincodeDeserializer, key)).unwrap();
+        journal.store_entries(vec!["secret".to_string(), "data".to_string()].into_iter()).unwrap();
+        assert_eq!(journal.load_entry(1).unwrap(), "data");
+        drop(journal);
+        let data = buffer.get_ref().clone();
+        assert!(!data.windows(6).any(|window| window == b"secret"));
+
+        let mut reader = JournalReader::<String, _, _>::with_deserializer(&mut buffer,
+            Encrypted::new(BincodeDeserializer, [8u8; 32])).unwrap();
+        assert!(matches!(reader.iter().next(), Some(Err(JournalError::SerializationError(EncryptionError::Decryption)))));
+        drop(reader);
+
+        // flip a bit in the ciphertext and fix up the frame checksum, like an attacker would
+        let mut tampered = data;
+        let payload_start = tampered.len() - (24 + 5 + 16);
+        *tampered.last_mut().unwrap() ^= 1;
+        let crc = Checksum::Crc32c.compute(&tampered[payload_start - 8..payload_start - 4], &tampered[payload_start..]);
+        tampered[payload_start - 4..payload_start].copy_from_slice(&crc.to_le_bytes());
+        let mut journal = IndexedJournal::<String, _, _, _>::with_serializer(Cursor::new(tampered),
+            Encrypted::new(BincodeSerializer, key), Encrypted::new(BincodeDeserializer, key)).unwrap();
+        assert_eq!(journal.load_entry(0).unwrap(), "secret");
+        assert!(matches!(journal.load_entry(1), Err(JournalError::SerializationError(EncryptionError::Decryption))));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_entries_are_bound_to_their_position() {
+        type Update = (u32, String);
+        let key = [7u8; 32];
+        let updates = || vec![(1, "aaaa".to_string()), (2, "bbbb".to_string()), (1, "cccc".to_string())];
+        let read_all = |data: Vec<u8>| JournalReader::<Update, _, _>::with_deserializer(Cursor::new(data),
+            Encrypted::new(BincodeDeserializer, key)).unwrap().iter().collect::<Vec<_>>();
+
+        // buffered entries and transactions know their offsets before they are written
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = JournalWriter::with_serializer(&mut buffer, Encrypted::new(BincodeSerializer, key)).unwrap()
+            .with_buffer_capacity(1024);
+        let mut entries = updates().into_iter();
+        writer.store_entry(entries.next().unwrap()).unwrap();
+        let mut transaction = writer.begin_transaction().unwrap();
+        transaction.store_entries(entries).unwrap();
+        transaction.commit().unwrap();
+        writer.finish().unwrap();
+        let values = read_all(buffer.into_inner()).into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(values, updates());
+
+        let mut buffer = Cursor::new(Vec::new());
+        let mut journal = IndexedJournal::with_serializer(&mut buffer,
+            Encrypted::new(BincodeSerializer, key), Encrypted::new(BincodeDeserializer, key)).unwrap();
+        journal.store_entries(updates().into_iter()).unwrap();
+        let mut transaction = journal.begin_transaction().unwrap();
+        transaction.store_entry((3, "dddd".to_string())).unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(journal.load_entry(3).unwrap(), (3, "dddd".to_string()));
+        drop(journal);
+
+        // the frames are valid, but the entries were moved
+        let data = buffer.into_inner();
+        let frame_size = 8 + 24 + 6 + 16;
+        let header_size = data.len() - 3 * frame_size - (13 + frame_size + 9);
+        let frame = |index: usize| &data[header_size + index * frame_size..header_size + (index + 1) * frame_size];
+        let swapped = [&data[..header_size], frame(1), frame(0), frame(2)].concat();
+        assert!(matches!(read_all(swapped)[0], Err(JournalError::SerializationError(EncryptionError::Decryption))));
+        let removed = [&data[..header_size], frame(0), frame(2)].concat();
+        let values = read_all(removed);
+        assert_eq!(values[0].as_ref().unwrap(), &(1, "aaaa".to_string()));
+        assert!(matches!(values[1], Err(JournalError::SerializationError(EncryptionError::Decryption))));
+
+        // another journal has another id, even if the entry is at the same offset
+        let mut other = Cursor::new(Vec::new());
+        JournalWriter::with_serializer(&mut other, Encrypted::new(BincodeSerializer, key)).unwrap()
+            .store_entries(updates().into_iter()).unwrap();
+        let other = other.into_inner();
+        let copied = [&other[..header_size], frame(0)].concat();
+        assert!(matches!(read_all(copied)[0], Err(JournalError::SerializationError(EncryptionError::Decryption))));
+        assert_eq!(read_all(other[..header_size + frame_size].to_vec())[0].as_ref().unwrap(), &(1, "aaaa".to_string()));
+
+        // compaction moves entries and encrypts them for their new offset
+        let path = std::env::temp_dir().join(format!("journal_file_{}_encrypted_compaction", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+        let dropped = compact_with_deserializer(&path, Encrypted::new(BincodeDeserializer, key), None,
+            |update: &Update| CompactionKey::Value(update.0)).unwrap();
+        assert_eq!(dropped, 1);
+        let values = read_all(std::fs::read(&path).unwrap()).into_iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(values, vec![(2, "bbbb".to_string()), (1, "cccc".to_string()), (3, "dddd".to_string())]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "encryption", feature = "lz4"))]
+    #[test]
+    fn test_compressed_encrypted_entries_are_relocated() {
+        use crate::codec::Lz4;
+        use crate::journal_reader::JournalDeserialize;
+
+        type Update = (u32, String);
+        type Deserializer = Compressed<Encrypted<BincodeDeserializer>, Lz4>;
+        const _: () = assert!(<Deserializer as JournalDeserialize<Update>>::DEPENDS_ON_POSITION);
+        const _: () = assert!(!<Compressed<BincodeDeserializer, Lz4> as JournalDeserialize<Update>>::DEPENDS_ON_POSITION);
+
+        let key = [7u8; 32];
+        let path = std::env::temp_dir().join(format!("journal_file_{}_compressed_encrypted_compaction", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        JournalWriter::<Update, _, _>::with_serializer(File::create(&path).unwrap(), Compressed::new(Encrypted::new(BincodeSerializer, key), Lz4)).unwrap()
+            .store_entries(vec![(1, "aaaa".to_string()), (2, "bbbb".to_string()), (1, "cccc".to_string())].into_iter()).unwrap();
+        let dropped = compact_with_deserializer(&path, Compressed::new(Encrypted::new(BincodeDeserializer, key), Lz4), None,
+            |update: &Update| CompactionKey::Value(update.0)).unwrap();
+        assert_eq!(dropped, 1);
+        let mut reader = JournalReader::<Update, _, _>::with_deserializer(File::open(&path).unwrap(),
+            Compressed::new(Encrypted::new(BincodeDeserializer, key), Lz4)).unwrap();
+        let values = reader.iter().map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(values, vec![(2, "bbbb".to_string()), (1, "cccc".to_string())]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_versioned_entries() {
+        type V1 = (u32, String);
+        type V2 = (u32, String, bool);
+        fn upcast_v1(deserializer: &BincodeDeserializer, reader: &mut dyn Read) -> Result<Option<V2>, bincode::Error> {
+            let old: Option<V1> = deserializer.deserialize(reader)?;
+            Ok(old.map(|(id, name)| (id, name, false)))
+        }
+        static UPCASTERS: &[Upcaster<V2, BincodeDeserializer>] = &[(1, upcast_v1)];
+
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = JournalWriter::<V1, _, _>::with_serializer(&mut buffer, VersionedSerializer::new(BincodeSerializer, 1)).unwrap();
+        writer.store_entry((1, "one".to_string())).unwrap();
+        drop(writer);
+
+        let v2 = VersionedDeserializer::new(BincodeDeserializer, 2);
+        let mut journal = IndexedJournal::<V2, _, _, _>::with_serializer(&mut buffer,
+            VersionedSerializer::new(BincodeSerializer, 2), v2.clone().with_upcasters(UPCASTERS)).unwrap();
+        journal.store_entry((2, "two".to_string(), true)).unwrap();
+        assert_eq!(journal.load_entry(0).unwrap(), (1, "one".to_string(), false));
+        let entries: Vec<V2> = journal.iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![(1, "one".to_string(), false), (2, "two".to_string(), true)]);
+        drop(journal);
+
+        let mut reader = JournalReader::<V2, _, _>::with_deserializer(&mut buffer, v2.clone()).unwrap();
+        assert!(matches!(reader.iter().next(), Some(Err(JournalError::SerializationError(VersionedError::UnknownVersion(1))))));
+        drop(reader);
+        assert!(matches!(SimpleJournalReader::<V2, _>::new(&mut buffer),
+            Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: false }))));
+
+        // a journal that was written before versioning was introduced holds version 0
+        fn upcast_v0(deserializer: &BincodeDeserializer, reader: &mut dyn Read) -> Result<Option<V2>, bincode::Error> {
+            let old: Option<u32> = deserializer.deserialize(reader)?;
+            Ok(old.map(|id| (id, String::new(), false)))
+        }
+        static ALL_UPCASTERS: &[Upcaster<V2, BincodeDeserializer>] = &[(0, upcast_v0), (1, upcast_v1)];
+        let mut buffer = Cursor::new(Vec::new());
+        SimpleJournalWriter::<u32, _>::new(&mut buffer).unwrap()
+            .store_entries(vec![7, 8].into_iter()).unwrap();
+        let mut reader = JournalReader::<V2, _, _>::with_deserializer(&mut buffer, v2.clone().with_upcasters(ALL_UPCASTERS)).unwrap();
+        let entries: Vec<V2> = reader.iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![(7, String::new(), false), (8, String::new(), false)]);
+        drop(reader);
+        let mut reader = JournalReader::<V2, _, _>::with_deserializer(&mut buffer, v2.clone().with_upcasters(ALL_UPCASTERS).with_unversioned(1)).unwrap();
+        assert!(reader.iter().next().unwrap().is_err());
+        drop(reader);
+        // new entries would be read as version 0 as well, so the journal is read only
+        let mut journal = IndexedJournal::<V2, _, _, _>::with_serializer(&mut buffer,
+            VersionedSerializer::new(BincodeSerializer, 2), v2.clone().with_upcaster(0, upcast_v0)).unwrap();
+        assert_eq!(journal.load_entry(1).unwrap(), (8, String::new(), false));
+        assert!(matches!(journal.store_entry((9, "nine".to_string(), true)),
+            Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: true }))));
+        drop(journal);
+        let mut journal = IndexedJournal::<V2, _, _, _>::with_serializer(&mut buffer,
+            BincodeSerializer, v2.clone().with_upcaster(0, upcast_v0)).unwrap();
+        assert!(matches!(journal.begin_transaction(),
+            Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: true }))));
+        drop(journal);
+
+        // until it was migrated
+        let path = std::env::temp_dir().join(format!("journal_file_{}_versioning_migration", std::process::id()));
+        std::fs::write(&path, buffer.get_ref()).unwrap();
+        let migrate = || migrate_to_versioned(&path, VersionedSerializer::new(BincodeSerializer, 2), v2.clone().with_upcaster(0, upcast_v0));
+        assert!(matches!(migrate_to_versioned::<V2, _, _, _>(&path, BincodeSerializer, BincodeDeserializer),
+            Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: false }))));
+        assert_eq!(migrate().unwrap(), 2);
+        assert_eq!(migrate().unwrap(), 0);
+        let mut journal = IndexedJournal::<V2, _, _, _>::with_serializer(OpenOptions::new().read(true).write(true).open(&path).unwrap(),
+            VersionedSerializer::new(BincodeSerializer, 2), v2.clone()).unwrap();
+        journal.store_entry((9, "nine".to_string(), true)).unwrap();
+        let entries: Vec<V2> = journal.iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec![(7, String::new(), false), (8, String::new(), false), (9, "nine".to_string(), true)]);
+        drop(journal);
+        assert!(matches!(SimpleJournalWriter::<V2, _>::new(OpenOptions::new().read(true).write(true).open(&path).unwrap()),
+            Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: false }))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_entries() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut journal = IndexedJournal::<(u32, String), _, _, _>::with_serializer(&mut buffer, JsonSerializer, JsonDeserializer).unwrap();
+        journal.store_entries(vec![(1, "one".to_string()), (2, "two".to_string())].into_iter()).unwrap();
+        assert_eq!(journal.load_entry(1).unwrap(), (2, "two".to_string()));
+        drop(journal);
+        assert!(buffer.get_ref().windows(10).any(|window| window == b"[1,\"one\"]\n"));
+
+        // a line that was cut off is reported like a partially written bincode entry
+        let is_partial = |mut line: &[u8]| matches!(JournalDeserialize::<(u32, String)>::deserialize(&JsonDeserializer, &mut line), Ok(None));
+        assert!(is_partial(b"[2,\"tw"));
+        assert!(is_partial(b"[2,\"two\"]"));
+        assert!(is_partial(b""));
+        assert!(!is_partial(b"[2,\"two\"]\n"));
+
+        // in a complete frame, a missing line break means the entry is corrupted
+        let mut data = buffer.get_ref().clone();
+        let offset = data.len() - 10 - 8;
+        data.truncate(data.len() - 1);
+        let length = 9u32.to_le_bytes();
+        let crc = Checksum::Crc32c.compute(&length, &data[offset + 8..]);
+        data[offset..offset + 4].copy_from_slice(&length);
+        data[offset + 4..offset + 8].copy_from_slice(&crc.to_le_bytes());
+        let mut journal = JsonIndexedJournal::<(u32, String), _>::with_serializer(Cursor::new(data), JsonSerializer, JsonDeserializer).unwrap();
+        assert!(matches!(journal.load_entry(1), Err(JournalError::Corrupted { offset: corrupted }) if corrupted == offset as u64));
+        assert!(matches!(SimpleJournalReader::<(u32, String), _>::new(&mut buffer),
+            Err(JournalError::InvalidHeader(HeaderError::FormatMismatch { .. }))));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_lines() {
+        type Event = (u32, String);
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = JsonLinesWriter::<Event, _>::new(&mut buffer).unwrap();
+        writer.store_entries(vec![(1, "one".to_string()), (2, "two\nlines".to_string())].into_iter()).unwrap();
+        writer.store_entry((3, "three".to_string())).unwrap();
+        drop(writer);
+        assert_eq!(buffer.get_ref(), b"[1,\"one\"]\n[2,\"two\\nlines\"]\n[3,\"three\"]\n");
+        let events = JsonLinesReader::<Event, _>::new(buffer.get_ref().as_slice()).map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(events, vec![(1, "one".to_string()), (2, "two\nlines".to_string()), (3, "three".to_string())]);
+
+        // a broken line is skipped, a partial last line ends the file like a dirty tail
+        let mut buffer = Cursor::new(b"[1,\"one\"]\n\n{oops}\n[2,\"two\"]\n[3,\"th".to_vec());
+        let events = JsonLinesReader::<Event, _>::new(buffer.get_ref().as_slice()).collect::<Vec<_>>();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0].as_ref().unwrap(), &(1, "one".to_string()));
+        assert!(matches!(events[1], Err(JournalError::SerializationError(_))));
+        assert_eq!(events[2].as_ref().unwrap(), &(2, "two".to_string()));
+        assert!(matches!(&events[3], Err(JournalError::IOError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof));
+
+        // appending would continue the partial line, so it has to be removed first
+        assert!(matches!(JsonLinesWriter::<Event, _>::new(&mut buffer), Err(JournalError::IOError(_))));
+        let (mut writer, tail) = JsonLinesWriter::<Event, _>::recover(&mut buffer).unwrap();
+        assert_eq!(tail, Some(TruncatedTail { offset: 28, dropped_bytes: 6 }));
+        writer.store_entry((3, "three".to_string())).unwrap();
+        drop(writer);
+        assert!(buffer.get_ref().ends_with(b"[2,\"two\"]\n[3,\"three\"]\n"));
+        assert_eq!(JsonLinesWriter::<Event, _>::recover(&mut buffer).unwrap().1, None);
+    }
+
+    #[cfg(all(feature = "msgpack", feature = "cbor", feature = "postcard"))]
+    #[test]
+    fn test_binary_formats() {
+        type Event = (u64, String);
+
+        fn roundtrip<S, D>(serializer: S, deserializer: D) -> Cursor<Vec<u8>>
+        where S: JournalSerialize<Event> + std::fmt::Debug, D: JournalDeserialize<Event> + std::fmt::Debug {
+            let mut buffer = Cursor::new(Vec::new());
+            let mut journal = IndexedJournal::with_serializer(&mut buffer, serializer, deserializer.clone()).unwrap();
+            journal.store_entry((1, "created".to_string())).unwrap();
+            journal.store_entry((2, "deleted".to_string())).unwrap();
+            assert_eq!(journal.load_entry(1).unwrap(), (2, "deleted".to_string()));
+            drop(journal);
+
+            let mut reader = JournalReader::with_deserializer(&mut buffer, deserializer).unwrap();
+            assert_eq!(reader.iter().map(|event| event.unwrap().0).collect::<Vec<_>>(), vec![1, 2]);
+            drop(reader);
+            buffer
+        }
+
+        let mut msgpack: MsgPackJournalReader<Event, _> = JournalReader::with_deserializer(
+            roundtrip(MsgPackSerializer, MsgPackDeserializer), MsgPackDeserializer).unwrap();
+        assert_eq!(msgpack.iter().count(), 2);
+        roundtrip(CborSerializer, CborDeserializer);
+        let mut postcard = roundtrip(PostcardSerializer, PostcardDeserializer);
+
+        // the format is recorded in the header
+        assert!(matches!(CborJournalReader::<Event, _>::with_deserializer(&mut postcard, CborDeserializer),
+            Err(JournalError::InvalidHeader(HeaderError::FormatMismatch { .. }))));
+
+        // a reader that fails is no partially written entry
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "disk on fire"))
+            }
+        }
+        let result = JournalDeserialize::<Event>::deserialize(&PostcardDeserializer, &mut Failing);
+        assert!(matches!(result, Err(PostcardError::IOError(_))));
+    }
+
+    #[test]
+    fn test_in_memory_storage() {
+        let mut buffer = Cursor::new(Vec::new());
+        SimpleJournalWriter::new(&mut buffer).unwrap()
+            .store_entries(vec!["hello".to_string(), "world".to_string()].into_iter())
+            .unwrap();
+
+        let mut journal = SimpleIndexedJournal::<String, _>::new(&mut buffer).unwrap();
+        assert_eq!(journal.store_entry("again".to_string()).unwrap(), 2);
+        assert_eq!(journal.load_entry(1).unwrap(), "world");
+        drop(journal);
+
+        let mut reader = SimpleJournalReader::<String, _>::new(buffer).unwrap();
+        let entries: Vec<String> = reader.iter().map(Result::unwrap).collect();
+        assert_eq!(entries, vec!["hello", "world", "again"]);
+
+        // truncating and syncing are optional
+        struct PlainStorage(Cursor<Vec<u8>>);
+        impl std::io::Read for PlainStorage {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> { self.0.read(buf) }
+        }
+        impl Write for PlainStorage {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> { self.0.write(buf) }
+            fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+        }
+        impl std::io::Seek for PlainStorage {
+            fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> { self.0.seek(pos) }
+        }
+        impl Storage for PlainStorage {}
+
+        let mut storage = PlainStorage(Cursor::new(Vec::new()));
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap()
+            .with_durability(Durability::EveryEntry);
+        writer.store_entry(1u32).unwrap();
+        drop(writer);
+        storage.0.get_mut().push(1);
+        assert!(matches!(SimpleJournalWriter::<u32, _>::recover(&mut storage),
+            Err(JournalError::IOError(err)) if err.kind() == std::io::ErrorKind::Unsupported));
+    }
+
+    /// In-memory storage that counts writes and syncs and can be made to fail syncs and writes.
+    #[derive(Debug, Default)]
+    struct TrackedStorage {
+        data: Cursor<Vec<u8>>,
+        writes: usize,
+        syncs: usize,
+        fail: bool,
+        /// Writes only write part of the data and fail then, shared to toggle it while the storage is borrowed
+        fail_writes: std::rc::Rc<std::cell::Cell<bool>>,
+        /// Truncating fails, shared like `fail_writes`
+        fail_truncates: std::rc::Rc<std::cell::Cell<bool>>,
+    }
+
+    impl std::io::Read for TrackedStorage {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> { self.data.read(buf) }
+    }
+
+    impl Write for TrackedStorage {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            if self.fail_writes.get() {
+                return match self.data.write(&buf[..buf.len() / 2])? {
+                    0 => Err(std::io::Error::new(std::io::ErrorKind::Other, "write failed")),
+                    written => Ok(written),
+                };
+            }
+            self.data.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+    }
+
+    impl std::io::Seek for TrackedStorage {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> { self.data.seek(pos) }
+    }
+
+    impl Storage for TrackedStorage {
+        fn truncate(&mut self, length: u64) -> std::io::Result<()> {
+            if self.fail_truncates.get() {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "truncate failed"));
+            }
+            self.data.truncate(length)
+        }
+        fn sync(&mut self) -> std::io::Result<()> {
+            if self.fail {
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, "sync failed"));
+            }
+            self.syncs += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_durability_policy() {
+        let mut storage = TrackedStorage::default();
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap()
+            .with_durability(Durability::Entries(2));
+        writer.store_entry(1u32).unwrap();
+        writer.store_entries(vec![2u32, 3].into_iter()).unwrap();
+        writer.store_entry(4u32).unwrap();
+        writer.sync().unwrap();
+        drop(writer);
+        assert_eq!(storage.syncs, 2);
+
+        storage.fail = true;
+        let mut journal = SimpleIndexedJournal::<u32, _>::new(&mut storage).unwrap()
+            .with_durability(Durability::EveryEntry);
+        assert!(matches!(journal.store_entry(5), Err(JournalError::SyncFailed { indices, .. }) if indices == (4..5)));
+        assert_eq!(journal.load_entry(4).unwrap(), 5);
+        drop(journal);
+
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap()
+            .with_durability(Durability::EveryEntry);
+        assert!(matches!(writer.store_entry(6u32), Err(JournalError::NotSynced(_))));
+        assert!(matches!(writer.sync(), Err(JournalError::NotSynced(_))));
+        drop(writer);
+        let mut reader = SimpleJournalReader::<u32, _>::new(&mut storage.data).unwrap();
+        assert_eq!(reader.iter().count(), 6);
+    }
+
+    #[test]
+    fn test_buffered_writer() {
+        let mut storage = TrackedStorage::default();
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap()
+            .with_buffer_capacity(1024);
+        writer.store_entries((0..10u32).map(|i| i.to_string())).unwrap();
+        writer.store_entry("last".to_string()).unwrap();
+        writer.flush().unwrap();
+        writer.store_entry("dropped".to_string()).unwrap();
+        drop(writer);
+        // the header and one write per flush
+        assert_eq!(storage.writes, 3);
+
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap();
+        writer.store_entries((0..10u32).map(|i| i.to_string())).unwrap();
+        drop(writer);
+        assert_eq!(storage.writes, 4);
+
+        let mut reader = SimpleJournalReader::<String, _>::new(&mut storage.data).unwrap();
+        assert_eq!(reader.iter().count(), 22);
+
+        // dropping a writer syncs its entries like a flush
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap()
+            .with_buffer_capacity(1024)
+            .with_durability(Durability::EveryEntry);
+        writer.store_entry("synced".to_string()).unwrap();
+        drop(writer);
+        assert_eq!(storage.syncs, 1);
+    }
+
+    #[test]
+    fn test_failed_write_is_rolled_back() {
+        let mut storage = TrackedStorage::default();
+        let fail_writes = storage.fail_writes.clone();
+        let mut writer = SimpleJournalWriter::new(&mut storage).unwrap()
+            .with_buffer_capacity(1024);
+        writer.store_entry(1u32).unwrap();
+        writer.flush().unwrap();
+        writer.store_entry(2u32).unwrap();
+        fail_writes.set(true);
+        assert!(matches!(writer.flush(), Err(JournalError::IOError(_))));
+        // the entry was discarded, so storing it again doesn't duplicate it
+        fail_writes.set(false);
+        writer.store_entry(2).unwrap();
+        writer.finish().unwrap();
+
+        // the partial frames can't be removed right away, the next write of the journal does it
+        let fail_truncates = storage.fail_truncates.clone();
+        let mut journal = SimpleIndexedJournal::<u32, _>::new(&mut storage).unwrap();
+        fail_writes.set(true);
+        fail_truncates.set(true);
+        assert!(matches!(journal.store_entry(3), Err(JournalError::IOError(_))));
+        fail_writes.set(false);
+        fail_truncates.set(false);
+        assert_eq!(journal.store_entry(4).unwrap(), 2);
+        drop(journal);
+
+        let mut reader = SimpleJournalReader::<u32, _>::new(&mut storage.data).unwrap();
+        let values = reader.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_transactions_are_atomic() {
+        let mut buffer = Cursor::new(Vec::new());
+        let mut writer = SimpleJournalWriter::new(&mut buffer).unwrap();
+        writer.store_entry(1u64).unwrap();
+        let mut transaction = writer.begin_transaction().unwrap();
+        transaction.store_entries(vec![2, 3].into_iter()).unwrap();
+        transaction.commit().unwrap();
+        let mut transaction = writer.begin_transaction().unwrap();
+        transaction.store_entry(4).unwrap();
+        drop(transaction);
+        drop(writer);
+
+        let start = buffer.get_ref().len();
+        let mut journal = SimpleIndexedJournal::<u64, _>::new(&mut buffer).unwrap();
+        let mut transaction = journal.begin_transaction().unwrap();
+        transaction.store_entries(vec![5, 6].into_iter()).unwrap();
+        assert_eq!(transaction.commit().unwrap(), 3..5);
+        assert_eq!(journal.load_entry(3).unwrap(), 5);
+        let values = journal.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![1, 2, 3, 5, 6]);
+        drop(journal);
+
+        // wherever the write is cut off, either the whole transaction is visible or nothing
+        let complete = buffer.get_ref().clone();
+        for cut in start..complete.len() {
+            let mut buffer = Cursor::new(complete[..cut].to_vec());
+            let values = SimpleJournalReader::<u64, _>::new(&mut buffer).unwrap().iter()
+                .filter_map(Result::ok)
+                .collect::<Vec<_>>();
+            assert_eq!(values, vec![1, 2, 3]);
+        }
+
+        buffer.get_mut().pop();
+        assert!(SimpleIndexedJournal::<u64, _>::new(&mut buffer).is_err());
+        let (mut journal, tail) = SimpleIndexedJournal::<u64, _>::recover(&mut buffer).unwrap();
+        assert!(tail.is_some());
+        let values = journal.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![1, 2, 3]);
+    }
 }
 //    pub fn test_journal_writer() {
 //        let mut raw_file = File::with_options()
@@ -0,0 +1,48 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+
+use memmap2::Mmap;
+
+use crate::frame::{self, Checksum, FrameError, FRAME_HEADER_SIZE};
+
+/// A read-only memory map of a journal file that grows with the file.
+#[derive(Debug)]
+pub(crate) struct MappedFile {
+    file: File,
+    /// `None` until something was mapped, empty files can't be mapped
+    map: Option<Mmap>,
+}
+
+impl MappedFile {
+    pub(crate) fn new(file: File) -> Self {
+        Self { file, map: None }
+    }
+
+    /// Verify the entry frame at `offset` and return its payload straight from the map.
+    pub(crate) fn entry(&mut self, offset: u64, checksum: Checksum) -> std::result::Result<&[u8], FrameError> {
+        let start = offset as usize;
+        let header_end = start + FRAME_HEADER_SIZE;
+        let length = frame::payload_length(tail(self.mapped(header_end)?, start)?)
+            .ok_or(FrameError::Torn)?;
+        frame::entry_at(tail(self.mapped(header_end + length)?, start)?, checksum)
+    }
+
+    /// The mapped file, which is mapped again if it doesn't reach `end` yet and the file grew.
+    ///
+    /// The map might still be shorter than `end` if the file is.
+    fn mapped(&mut self, end: usize) -> Result<&[u8]> {
+        let mapped_len = self.map.as_ref().map_or(0, |map| map.len());
+        // mapping a file that is shorter than the index says is pointless, and would happen on every read
+        if mapped_len < end && self.file.metadata()?.len() > mapped_len as u64 {
+            // the file only grows while the journal is open, entries are never changed
+            self.map = Some(unsafe { Mmap::map(&self.file)? });
+        }
+        Ok(self.map.as_deref().unwrap_or_default())
+    }
+}
+
+/// The part of `map` from `start` on, the file might have been truncated behind our back.
+fn tail(map: &[u8], start: usize) -> Result<&[u8]> {
+    map.get(start..)
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "Journal entry starts beyond the end of the file"))
+}
@@ -1,6 +1,8 @@
 use std::io::{Read, Seek, SeekFrom};
 use std::ops::{DerefMut, Deref};
 
+use crate::Storage;
+
 #[derive(Debug)]
 pub enum OwnedOrRef<'a, T> {
     Ref(&'a mut T),
@@ -34,28 +36,34 @@ where X: Seek {
     }
 }
 
-impl<T> From<T> for OwnedOrRef<'static, T> {
+// Bounded by `Storage`, so a `&mut T` can't be taken as owned storage,
+// which would make the storage type ambiguous.
+impl<T> From<T> for OwnedOrRef<'static, T>
+where T: Storage {
     fn from(value: T) -> Self {
         OwnedOrRef::Owned(value)
     }
 }
-impl<'a, T> From<&'a mut T> for OwnedOrRef<'a, T> {
+impl<'a, T> From<&'a mut T> for OwnedOrRef<'a, T>
+where T: Storage {
     fn from(value: &'a mut T) -> Self {
         OwnedOrRef::Ref(value)
     }
 }
 
 impl<'a, T> OwnedOrRef<'a, T> {
+    #[allow(clippy::should_implement_trait)]
     pub fn as_mut(&mut self) -> &mut T {
         match self {
-            OwnedOrRef::Ref(reference) => *reference,
+            OwnedOrRef::Ref(reference) => reference,
             OwnedOrRef::Owned(owned) => owned,
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn as_ref(&self) -> &T {
         match self {
-            OwnedOrRef::Ref(reference) => *reference,
+            OwnedOrRef::Ref(reference) => reference,
             OwnedOrRef::Owned(owned) => owned,
         }
     }
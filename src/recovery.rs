@@ -0,0 +1,109 @@
+use std::io::{SeekFrom, BufReader};
+
+use crate::*;
+use crate::frame::FrameError;
+use crate::header::FileHeader;
+
+/// Describes the partially written tail that was removed when a journal was recovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncatedTail {
+    /// The byte offset the file was truncated at
+    pub offset: u64,
+    /// The number of bytes that were dropped
+    pub dropped_bytes: u64,
+}
+
+/// Find the end of the last complete and valid entry and truncate everything after it.
+///
+/// Only the tail of the file is dropped. If an invalid entry is followed by
+/// valid ones, the file is corrupted instead and left untouched. This also
+/// holds if a corrupted length makes an entry look like it runs past the end
+/// of the file, the bytes after it are searched for valid entries.
+///
+/// Without checksums, valid entries can't be told apart from garbage, so
+/// only an unfinished last entry is dropped. The zeros a power loss can
+/// leave at the end of such a file are read as empty entries and kept.
+pub(crate) fn truncate_torn_tail<F, E>(file: &mut F) -> Result<Option<TruncatedTail>, JournalError<E>>
+where F: Storage {
+    let length = file.seek(SeekFrom::End(0))
+        .map_err(JournalError::IOError)?;
+
+    let header = match FileHeader::read(file) {
+        Ok(Some(header)) => header,
+        Ok(None) => return Ok(None),
+        Err(JournalError::InvalidHeader(HeaderError::Malformed(_)))
+            if FileHeader::is_torn(file).map_err(JournalError::IOError)? => {
+            // the crash happened while the journal was created
+            return truncate(file, 0, length);
+        },
+        Err(err) => return Err(err),
+    };
+
+    let data_start = header.size();
+    file.seek(SeekFrom::Start(data_start))
+        .map_err(JournalError::IOError)?;
+    let mut reader = CountingIO::new(BufReader::new(&mut *file)).with_offset(data_start);
+    let mut entries = frame::EntryReader::default();
+    let mut invalid_offset = None;
+
+    loop {
+        // an unfinished transaction is dropped as a whole
+        match entries.next(&mut reader, header.checksum) {
+            Ok(Some(_)) => {
+                if let Some(offset) = invalid_offset {
+                    return Err(JournalError::Corrupted { offset });
+                }
+            },
+            Ok(None) => break,
+            Err((FrameError::Torn, offset)) => {
+                invalid_offset.get_or_insert(offset);
+                break;
+            },
+            // this might still be the tail, if no valid entry follows
+            Err((FrameError::Corrupted, offset)) => {
+                invalid_offset.get_or_insert(offset);
+            },
+            Err((FrameError::IOError(err), _)) => return Err(JournalError::IOError(err)),
+        }
+    }
+
+    let offset = match invalid_offset {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+    if header.checksum != Checksum::None && valid_frame_follows(file, offset, header.checksum)? {
+        return Err(JournalError::Corrupted { offset });
+    }
+    truncate(file, offset, length)
+}
+
+/// Search the bytes after the invalid frame at `offset` for a valid frame.
+///
+/// Reading frame by frame can't find them if the invalid frame has a wrong
+/// length, so every byte position is tried.
+fn valid_frame_follows<F, E>(file: &mut F, offset: u64, checksum: Checksum) -> Result<bool, JournalError<E>>
+where F: Storage {
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_to_end(&mut tail))
+        .map_err(JournalError::IOError)?;
+
+    // skip the complete frames of the transaction the invalid frame belongs to
+    let mut invalid = 0;
+    while let Ok(Some((_, size))) = frame::frame_at(&tail[invalid..], checksum) {
+        invalid += size;
+    }
+    Ok((invalid + 1..tail.len())
+        .any(|position| matches!(frame::frame_at(&tail[position..], checksum), Ok(Some(_)))))
+}
+
+fn truncate<F, E>(file: &mut F, offset: u64, length: u64) -> Result<Option<TruncatedTail>, JournalError<E>>
+where F: Storage {
+    file.truncate(offset)
+        .and_then(|_| file.sync())
+        .map_err(JournalError::IOError)?;
+    Ok(Some(TruncatedTail {
+        offset,
+        dropped_bytes: length - offset,
+    }))
+}
@@ -0,0 +1,54 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Rules for removing the oldest entries of a [`SegmentedJournal`](crate::segmented_journal::SegmentedJournal).
+///
+/// Entries are removed segment by segment, starting with the oldest one.
+/// The segment new entries are appended to is never removed, so a journal
+/// can hold more than the limits allow until it rolls over to a new segment.
+/// Since only whole segments are removed, fewer entries than the limits allow
+/// might remain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Retention {
+    pub(crate) max_bytes: Option<u64>,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) max_entries: Option<usize>,
+    pub(crate) archive: Option<PathBuf>,
+}
+
+impl Retention {
+    /// Keep everything, add limits with the other methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove old segments while all segments together are larger than `bytes`.
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Remove segments whose newest entry was written more than `age` ago.
+    ///
+    /// The age is taken from the modification time of the segment file.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Remove old segments while the journal holds more than `entries` entries.
+    pub fn max_entries(mut self, entries: usize) -> Self {
+        self.max_entries = Some(entries);
+        self
+    }
+
+    /// Move removed segment files to `directory` instead of deleting them.
+    ///
+    /// The directory has to be on the same file system as the journal. Files
+    /// in it are never replaced, if a segment with the same name was archived
+    /// before, a number is added to the name of the new one.
+    pub fn archive_to<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.archive = Some(directory.as_ref().to_path_buf());
+        self
+    }
+}
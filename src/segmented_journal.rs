@@ -0,0 +1,528 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
+use std::ops::{Range, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::fmt::Debug;
+
+use crate::*;
+use crate::indexed_journal::resolve_range;
+
+/// Segment files are named after the index of their first entry.
+const SEGMENT_EXTENSION: &str = "journal";
+
+/// A journal that is split into segment files in a directory.
+///
+/// Entries are appended to the newest segment until it reaches the configured
+/// size or number of entries, then a new segment is started. Every segment
+/// file is named after the index of its first entry, so entry indices
+/// continue across segment boundaries.
+///
+/// All segments are kept open while the journal is in use.
+#[derive(Debug)]
+pub struct SegmentedJournal<T, S, D> {
+    directory: PathBuf,
+    serializer: S,
+    deserializer: D,
+    segments: Vec<Segment<T, S, D>>,
+    max_segment_bytes: Option<u64>,
+    max_segment_entries: Option<usize>,
+    /// The checksum for new segments
+    checksum: Checksum,
+    durability: Durability,
+    retention: Retention,
+}
+
+#[derive(Debug)]
+struct Segment<T, S, D> {
+    /// The index of the first entry
+    base: usize,
+    journal: IndexedJournal<'static, T, S, D, File>,
+}
+
+impl<T, S, D> Segment<T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    fn end(&self) -> usize {
+        self.base + self.journal.len()
+    }
+}
+
+impl<T> SegmentedJournal<T, BincodeSerializer, BincodeDeserializer>
+where T: serde::Serialize + for<'de> serde::Deserialize<'de> + Debug {
+    /// Open the segmented journal in `directory`, which is created if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, JournalError<bincode::Error>> {
+        Self::open_with_serializer(directory, BincodeSerializer, BincodeDeserializer)
+    }
+
+    /// Like [`open`](SegmentedJournal::open), but a partially written tail of
+    /// the newest segment is truncated first.
+    pub fn recover<P: AsRef<Path>>(directory: P) -> Result<(Self, Option<TruncatedTail>), JournalError<bincode::Error>> {
+        Self::recover_with_serializer(directory, BincodeSerializer, BincodeDeserializer)
+    }
+}
+
+impl<T, S, D> SegmentedJournal<T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    /// Like [`open`](SegmentedJournal::open), but you can provide your own serializer and deserializer.
+    pub fn open_with_serializer<P: AsRef<Path>>(directory: P, serializer: S, deserializer: D) -> Result<Self, JournalError<D::Error>> {
+        Self::open_segments(directory.as_ref(), serializer, deserializer, false)
+            .map(|(journal, _)| journal)
+    }
+
+    /// Like [`recover`](SegmentedJournal::recover), but you can provide your own serializer and deserializer.
+    pub fn recover_with_serializer<P: AsRef<Path>>(directory: P, serializer: S, deserializer: D) -> Result<(Self, Option<TruncatedTail>), JournalError<D::Error>> {
+        Self::open_segments(directory.as_ref(), serializer, deserializer, true)
+    }
+
+    fn open_segments(directory: &Path, serializer: S, deserializer: D, recover: bool) -> Result<(Self, Option<TruncatedTail>), JournalError<D::Error>> {
+        fs::create_dir_all(directory)
+            .map_err(JournalError::IOError)?;
+        let mut bases = Vec::new();
+        for dir_entry in fs::read_dir(directory).map_err(JournalError::IOError)? {
+            let path = dir_entry.map_err(JournalError::IOError)?.path();
+            if let Some(base) = segment_base(&path) {
+                bases.push(base);
+            }
+        }
+        bases.sort_unstable();
+
+        let mut segments: Vec<Segment<T, S, D>> = Vec::with_capacity(bases.len());
+        let mut tail = None;
+        for (position, &base) in bases.iter().enumerate() {
+            if segments.last().is_some_and(|last| last.end() != base) {
+                return Err(JournalError::IOError(std::io::Error::new(ErrorKind::InvalidData,
+                    "Journal segments are not contiguous")));
+            }
+            let file = open_segment_file(&segment_path(directory, base), false)
+                .map_err(JournalError::IOError)?;
+            // only the newest segment can have been written when the process crashed
+            let journal = if recover && position + 1 == bases.len() {
+                let (journal, truncated) = IndexedJournal::recover_with_serializer(file, serializer.clone(), deserializer.clone())?;
+                tail = truncated;
+                journal
+            } else {
+                IndexedJournal::with_serializer(file, serializer.clone(), deserializer.clone())?
+            };
+            segments.push(Segment { base, journal });
+        }
+
+        Ok((Self {
+            directory: directory.to_path_buf(),
+            serializer,
+            deserializer,
+            segments,
+            max_segment_bytes: None,
+            max_segment_entries: None,
+            checksum: Checksum::default(),
+            durability: Durability::default(),
+            retention: Retention::default(),
+        }, tail))
+    }
+
+    /// Start a new segment once the current one holds `bytes` bytes.
+    pub fn with_max_segment_bytes(mut self, bytes: u64) -> Self {
+        self.max_segment_bytes = Some(bytes);
+        self
+    }
+
+    /// Start a new segment once the current one holds `entries` entries.
+    pub fn with_max_segment_entries(mut self, entries: usize) -> Self {
+        self.max_segment_entries = Some(entries);
+        self
+    }
+
+    /// Select the checksum that entries in new segments are written with.
+    /// Defaults to [`Checksum::Crc32c`](Checksum::Crc32c).
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self.segments = self.segments.into_iter()
+            .map(|segment| Segment {
+                base: segment.base,
+                journal: segment.journal.with_checksum(checksum),
+            })
+            .collect();
+        self
+    }
+
+    /// Select when stored entries are synced to stable storage.
+    /// Defaults to [`Durability::Never`](Durability::Never).
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self.segments = self.segments.into_iter()
+            .map(|segment| Segment {
+                base: segment.base,
+                journal: segment.journal.with_durability(durability),
+            })
+            .collect();
+        self
+    }
+
+    /// Remove old segments according to `retention` whenever a new segment is started.
+    ///
+    /// Call [`enforce_retention`](SegmentedJournal::enforce_retention) to apply it right away.
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// The index the next entry will get.
+    ///
+    /// Removed entries are counted as well, since indices never change.
+    pub fn len(&self) -> usize {
+        self.segments.last().map_or(0, Segment::end)
+    }
+
+    /// The index of the oldest entry that wasn't removed by the retention policy.
+    pub fn first_index(&self) -> usize {
+        self.segments.first().map_or(0, |segment| segment.base)
+    }
+
+    /// Whether no entries are left, either none were stored or all of them were removed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == self.first_index()
+    }
+
+    /// The number of segment files.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    pub fn load_entry(&mut self, index: usize) -> Result<T, JournalError<D::Error>> {
+        let position = self.segment_position(index)?;
+        let segment = &mut self.segments[position];
+        segment.journal.load_entry(index - segment.base)
+    }
+
+    pub fn iter(&mut self) -> SegmentedJournalIter<'_, T, S, D> {
+        SegmentedJournalIter {
+            current: None,
+            segments: self.segments.iter_mut(),
+        }
+    }
+
+    pub fn iter_from(&mut self, index: usize) -> Result<SegmentedJournalIter<'_, T, S, D>, JournalError<D::Error>> {
+        let position = self.segment_position(index)?;
+        let (segment, rest) = self.segments[position..].split_first_mut().unwrap();
+        let current = segment.journal.iter_from(index - segment.base)?;
+        Ok(SegmentedJournalIter {
+            current: Some(current),
+            segments: rest.iter_mut(),
+        })
+    }
+
+    /// Iterate over the entries from the newest to the oldest that is left.
+    ///
+    /// Every entry is looked up in its segment, so this doesn't read the entries before.
+    pub fn iter_rev(&mut self) -> std::iter::Rev<SegmentedJournalRange<'_, T, S, D>> {
+        SegmentedJournalRange {
+            indices: self.first_index()..self.len(),
+            journal: self,
+        }.rev()
+    }
+
+    /// Iterate over the entries in `range`, which may span several segments.
+    ///
+    /// An unbounded start is the oldest entry that is left. Like
+    /// [`IndexedJournal::range`](crate::indexed_journal::IndexedJournal::range),
+    /// entries that are appended later aren't returned and every entry is
+    /// looked up on its own. Fails if the range exceeds the journal or starts
+    /// at a removed entry.
+    pub fn range<R>(&mut self, range: R) -> Result<SegmentedJournalRange<'_, T, S, D>, JournalError<D::Error>>
+    where R: RangeBounds<usize> {
+        let indices = resolve_range(&range, self.first_index(), self.len());
+        if indices.start > indices.end || indices.end > self.len() {
+            return Err(JournalError::IndexOutOfBounds);
+        }
+        if indices.start < self.first_index() {
+            return Err(JournalError::EntryRemoved { first_index: self.first_index() });
+        }
+        Ok(SegmentedJournalRange {
+            indices,
+            journal: self,
+        })
+    }
+
+    /// Find the segment that contains the entry at `index`.
+    fn segment_position(&self, index: usize) -> Result<usize, JournalError<D::Error>> {
+        if index < self.first_index() {
+            return Err(JournalError::EntryRemoved { first_index: self.first_index() });
+        }
+        let position = self.segments.partition_point(|segment| segment.base <= index);
+        match position.checked_sub(1) {
+            Some(position) if index < self.segments[position].end() => Ok(position),
+            _ => Err(JournalError::IndexOutOfBounds),
+        }
+    }
+
+    /// Append an entry and return its index.
+    pub fn store_entry(&mut self, entry: T) -> Result<usize, JournalError<S::Error>> {
+        self.store_entries(std::iter::once(entry))
+            .map(|indices| indices.start)
+    }
+
+    /// Append all entries to the current segment and return the range of their indices.
+    ///
+    /// The segment is only rolled over between calls, so all entries end up in the same segment.
+    pub fn store_entries<I>(&mut self, entries: I) -> Result<Range<usize>, JournalError<S::Error>>
+    where I: Iterator<Item=T> {
+        let segment = self.active_segment()?;
+        let base = segment.base;
+        to_global(base, segment.journal.store_entries(entries))
+    }
+
+    /// Start a transaction. Its entries are written when it is committed and
+    /// discarded if it is dropped before.
+    ///
+    /// All entries of a transaction end up in the same segment.
+    pub fn begin_transaction(&mut self) -> Result<SegmentedTransaction<'_, T, S, D>, JournalError<S::Error>> {
+        let segment = self.active_segment()?;
+        Ok(SegmentedTransaction {
+            base: segment.base,
+            transaction: segment.journal.begin_transaction()?,
+        })
+    }
+
+    /// Flush everything stored so far to stable storage, regardless of the durability policy.
+    pub fn sync(&mut self) -> Result<(), JournalError<S::Error>> {
+        match self.segments.last_mut() {
+            Some(segment) => segment.journal.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// The segment new entries are appended to, a new one is started if the last one is full.
+    fn active_segment(&mut self) -> Result<&mut Segment<T, S, D>, JournalError<S::Error>> {
+        let is_full = match self.segments.last() {
+            Some(segment) => !segment.journal.is_empty() && (
+                self.max_segment_bytes.is_some_and(|max| segment.journal.data_end() >= max)
+                    || self.max_segment_entries.is_some_and(|max| segment.journal.len() >= max)),
+            None => true,
+        };
+        if is_full {
+            if let Some(segment) = self.segments.last_mut() {
+                // entries in older segments have to be durable before new ones are written
+                segment.journal.sync()?;
+            }
+            let base = self.len();
+            let path = segment_path(&self.directory, base);
+            let file = open_segment_file(&path, true)
+                .map_err(JournalError::IOError)?;
+            let journal = IndexedJournal::create(file.into(), self.serializer.clone(), self.deserializer.clone())
+                .with_checksum(self.checksum)
+                .with_durability(self.durability);
+            self.segments.push(Segment { base, journal });
+            // the segment would be lost with all its entries if its directory entry isn't durable,
+            // it stays empty if this fails and is used by the next call
+            storage::sync_directory(&path)
+                .map_err(JournalError::IOError)?;
+            self.remove_segments()?;
+        }
+        Ok(self.segments.last_mut().unwrap())
+    }
+
+    /// Remove the oldest segments that the retention policy doesn't allow to keep.
+    ///
+    /// Returns the number of removed segments.
+    pub fn enforce_retention(&mut self) -> Result<usize, JournalError<S::Error>> {
+        self.remove_segments()
+    }
+
+    fn remove_segments<E>(&mut self) -> Result<usize, JournalError<E>> {
+        let mut total_bytes: u64 = self.segments.iter()
+            .map(|segment| segment.journal.data_end())
+            .sum();
+        let mut removed = 0;
+        let mut last_removed = None;
+        let mut last_archived = None;
+
+        // the oldest segment goes first, so the remaining ones are always contiguous
+        while self.segments.len() > 1 {
+            let oldest = &self.segments[0];
+            let path = segment_path(&self.directory, oldest.base);
+            let too_large = self.retention.max_bytes.is_some_and(|max| total_bytes > max);
+            let too_many = self.retention.max_entries.is_some_and(|max| self.len() - oldest.base > max);
+            let too_old = match self.retention.max_age {
+                Some(max_age) => fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(JournalError::IOError)?
+                    .elapsed()
+                    .is_ok_and(|age| age > max_age),
+                None => false,
+            };
+            if !(too_large || too_many || too_old) {
+                break;
+            }
+
+            total_bytes -= oldest.journal.data_end();
+            // only forget the segment once it is gone, otherwise it would come back on the next open
+            match self.retention.archive {
+                Some(ref archive) => {
+                    let target = archive_path(archive, oldest.base);
+                    fs::create_dir_all(archive)
+                        .and_then(|()| fs::rename(&path, &target))
+                        .map_err(JournalError::IOError)?;
+                    last_archived = Some(target);
+                },
+                None => fs::remove_file(&path)
+                    .map_err(JournalError::IOError)?,
+            }
+            self.segments.remove(0);
+            removed += 1;
+            last_removed = Some(path);
+        }
+
+        if let Some(path) = last_removed {
+            // the removal is only durable once the directories are synced
+            storage::sync_directory(&path)
+                .map_err(JournalError::IOError)?;
+        }
+        if let Some(target) = last_archived {
+            storage::sync_directory(&target)
+                .map_err(JournalError::IOError)?;
+        }
+        Ok(removed)
+    }
+}
+
+/// Iterates over the entries of all segments, see [`iter`](SegmentedJournal::iter).
+pub struct SegmentedJournalIter<'a, T, S, D> {
+    current: Option<IndexedJournalIter<'static, 'a, T, S, D, File>>,
+    segments: std::slice::IterMut<'a, Segment<T, S, D>>,
+}
+
+impl<'a, T, S, D> Iterator for SegmentedJournalIter<'a, T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.current.as_mut().and_then(Iterator::next) {
+                return Some(entry);
+            }
+            // drop the last iterator first, it gives the file back to its segment
+            self.current = None;
+            self.current = Some(self.segments.next()?.journal.iter());
+        }
+    }
+}
+
+/// Iterates over a range of entries, see [`range`](SegmentedJournal::range).
+pub struct SegmentedJournalRange<'a, T, S, D> {
+    journal: &'a mut SegmentedJournal<T, S, D>,
+    /// The indices of the entries that weren't returned yet
+    indices: Range<usize>,
+}
+
+impl<'a, T, S, D> Iterator for SegmentedJournalRange<'a, T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    type Item = Result<T, JournalError<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.indices.next()
+            .map(|index| self.journal.load_entry(index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<'a, T, S, D> DoubleEndedIterator for SegmentedJournalRange<'a, T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.indices.next_back()
+            .map(|index| self.journal.load_entry(index))
+    }
+}
+
+impl<'a, T, S, D> ExactSizeIterator for SegmentedJournalRange<'a, T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {}
+
+/// A group of entries that is written completely or not at all,
+/// see [`Transaction`](crate::journal_writer::Transaction).
+#[derive(Debug)]
+pub struct SegmentedTransaction<'a, T, S, D> {
+    transaction: IndexedTransaction<'a, 'static, T, S, D, File>,
+    /// The index of the first entry of the segment the transaction is written to
+    base: usize,
+}
+
+impl<'a, T, S, D> SegmentedTransaction<'a, T, S, D>
+where S: JournalSerialize<T> + Debug,
+      T: Debug {
+    pub fn store_entry(&mut self, entry: T) -> Result<(), JournalError<S::Error>> {
+        self.transaction.store_entry(entry)
+    }
+
+    /// Add entries to the transaction. If one of them can't be serialized,
+    /// none of them is added, but the transaction can still be committed.
+    pub fn store_entries<I>(&mut self, entries: I) -> Result<(), JournalError<S::Error>>
+    where I: Iterator<Item=T> {
+        self.transaction.store_entries(entries)
+    }
+
+    /// Write all entries of the transaction and return the range of their indices.
+    pub fn commit(self) -> Result<Range<usize>, JournalError<S::Error>> {
+        to_global(self.base, self.transaction.commit())
+    }
+}
+
+/// Turn the indices within the segment starting at `base` into indices of the whole journal.
+fn to_global<E>(base: usize, result: Result<Range<usize>, JournalError<E>>) -> Result<Range<usize>, JournalError<E>> {
+    let global = |indices: Range<usize>| base + indices.start..base + indices.end;
+    result
+        .map(global)
+        .map_err(|err| match err {
+            JournalError::SyncFailed { indices, error } => JournalError::SyncFailed { indices: global(indices), error },
+            err => err,
+        })
+}
+
+fn segment_path(directory: &Path, base: usize) -> PathBuf {
+    directory.join(format!("{:020}.{}", base, SEGMENT_EXTENSION))
+}
+
+/// Where the segment starting at `base` is moved to in `archive`.
+///
+/// A file that is already archived under that name, e.g. from another
+/// journal that shares the archive, is never replaced, a number is appended instead.
+fn archive_path(archive: &Path, base: usize) -> PathBuf {
+    let mut path = segment_path(archive, base);
+    let mut number = 0;
+    while fs::symlink_metadata(&path).is_ok() {
+        number += 1;
+        path = archive.join(format!("{:020}.{}.{}", base, number, SEGMENT_EXTENSION));
+    }
+    path
+}
+
+/// The index of the first entry if `path` is a segment file.
+fn segment_base(path: &Path) -> Option<usize> {
+    if path.extension()? != SEGMENT_EXTENSION {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    if stem.len() != 20 || !stem.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    stem.parse().ok()
+}
+
+fn open_segment_file(path: &Path, create: bool) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(create)
+        .open(path)
+}
@@ -0,0 +1,245 @@
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::fmt::Debug;
+
+use crate::*;
+
+/// Snapshot files are named after the number of entries they cover.
+const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+/// Stores snapshots of a state that is built from the entries of a journal.
+///
+/// Every snapshot is saved together with the index of the first entry that
+/// isn't part of it yet. Restoring the state loads the newest valid snapshot
+/// and only replays the entries after it, see [`restore`](Snapshots::restore).
+///
+/// Snapshots are written to a temporary file first and only renamed once
+/// they are on stable storage, so a crash never leaves a partial snapshot behind.
+#[derive(Debug)]
+pub struct Snapshots<T, S, D> {
+    directory: PathBuf,
+    serializer: S,
+    deserializer: D,
+    /// The number of snapshots that are kept when a new one is saved
+    keep: usize,
+    type_phantom: PhantomData<*const T>,
+}
+
+impl<T> Snapshots<T, BincodeSerializer, BincodeDeserializer>
+where T: serde::Serialize + for<'de> serde::Deserialize<'de> + Debug {
+    /// Store snapshots in `directory`, which is created if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Result<Self, JournalError<bincode::Error>> {
+        Self::open_with_serializer(directory, BincodeSerializer, BincodeDeserializer)
+    }
+}
+
+impl<T, S, D> Snapshots<T, S, D>
+where S: JournalSerialize<T> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug {
+    /// Like [`open`](Snapshots::open), but you can provide your own serializer and deserializer.
+    pub fn open_with_serializer<P: AsRef<Path>>(directory: P, serializer: S, deserializer: D) -> Result<Self, JournalError<D::Error>> {
+        fs::create_dir_all(directory.as_ref())
+            .map_err(JournalError::IOError)?;
+        Ok(Self {
+            directory: directory.as_ref().to_path_buf(),
+            serializer,
+            deserializer,
+            keep: 2,
+            type_phantom: PhantomData,
+        })
+    }
+
+    /// Keep the `count` most recently saved snapshots and delete older ones whenever a snapshot is saved.
+    ///
+    /// Defaults to 2, so there is an older snapshot to fall back to if the newest one is corrupted.
+    /// The snapshot that was just saved is always kept, even if its index is lower than the others.
+    pub fn with_keep(mut self, count: usize) -> Self {
+        self.keep = count.max(1);
+        self
+    }
+
+    /// Save `state`, which contains the effects of all entries before `index`.
+    pub fn save(&self, state: T, index: usize) -> Result<(), JournalError<S::Error>> {
+        let path = snapshot_path(&self.directory, index);
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+
+        let file = File::create(&temp_path)
+            .map_err(JournalError::IOError)?;
+        let mut writer = JournalWriter::with_serializer(file, self.serializer.clone())?;
+        writer.store_entry(state)?;
+        writer.sync()?;
+        drop(writer);
+        fs::rename(&temp_path, &path)
+            .and_then(|()| storage::sync_directory(&path))
+            .map_err(JournalError::IOError)?;
+
+        self.prune(index)
+            .map_err(JournalError::IOError)
+    }
+
+    /// Delete all but the `keep` most recently saved snapshots, `saved` is never deleted.
+    fn prune(&self, saved: usize) -> std::io::Result<()> {
+        let mut snapshots = Vec::new();
+        for index in self.indices()? {
+            if index != saved {
+                let modified = fs::metadata(snapshot_path(&self.directory, index))?.modified()?;
+                snapshots.push((modified, index));
+            }
+        }
+        // newest first, the index decides between snapshots saved at the same time
+        snapshots.sort_unstable_by(|a, b| b.cmp(a));
+        for (_, index) in snapshots.into_iter().skip(self.keep - 1) {
+            fs::remove_file(snapshot_path(&self.directory, index))?;
+        }
+        Ok(())
+    }
+
+    /// Load the newest snapshot that isn't corrupted, together with the index it was saved with.
+    pub fn load_latest(&self) -> Result<Option<(T, usize)>, JournalError<D::Error>> {
+        self.load_up_to(usize::MAX)
+    }
+
+    /// Rebuild the state from the newest valid snapshot and the entries of `journal` after it.
+    ///
+    /// Every entry that isn't part of the snapshot is passed to `apply`.
+    /// Snapshots that cover more entries than the journal holds are skipped.
+    /// Without a usable snapshot, all entries are applied to `initial`.
+    ///
+    /// The journal may use another deserializer than the snapshots, its
+    /// deserialization errors are returned as an `IOError` of kind `InvalidData`
+    /// that wraps the original error.
+    pub fn restore<E, JS, JD, F, A>(&self, journal: &mut IndexedJournal<'_, E, JS, JD, F>, initial: T, apply: A) -> Result<T, JournalError<D::Error>>
+    where JS: JournalSerialize<E> + Debug,
+          JD: JournalDeserialize<E> + Debug,
+          JD::Error: Send + Sync + 'static,
+          E: Debug,
+          F: Storage,
+          A: FnMut(&mut T, E) {
+        let len = journal.len();
+        self.replay(0, len, initial, move |index| journal.iter_from(index), apply)
+    }
+
+    /// Like [`restore`](Snapshots::restore), but for a [`SegmentedJournal`](SegmentedJournal).
+    ///
+    /// If the retention policy removed entries after the newest usable
+    /// snapshot, the state can't be rebuilt and
+    /// [`EntryRemoved`](JournalError::EntryRemoved) is returned.
+    pub fn restore_segmented<E, JS, JD, A>(&self, journal: &mut SegmentedJournal<E, JS, JD>, initial: T, apply: A) -> Result<T, JournalError<D::Error>>
+    where JS: JournalSerialize<E> + Debug,
+          JD: JournalDeserialize<E> + Debug,
+          JD::Error: Send + Sync + 'static,
+          E: Debug,
+          A: FnMut(&mut T, E) {
+        let first_index = journal.first_index();
+        let len = journal.len();
+        self.replay(first_index, len, initial, move |index| journal.iter_from(index), apply)
+    }
+
+    /// Load the newest snapshot that is valid for a journal with the entries
+    /// from `first_index` to `len` and apply the entries that `iter_from`
+    /// yields from the snapshot's index on.
+    fn replay<E, JE, I, R, A>(&self, first_index: usize, len: usize, initial: T, iter_from: R, mut apply: A) -> Result<T, JournalError<D::Error>>
+    where JE: std::error::Error + Send + Sync + 'static,
+          I: Iterator<Item = Result<E, JournalError<JE>>>,
+          R: FnOnce(usize) -> Result<I, JournalError<JE>>,
+          A: FnMut(&mut T, E) {
+        // older snapshots would need entries that were removed
+        let (mut state, index) = match self.load_between(first_index, len)? {
+            Some(snapshot) => snapshot,
+            None if first_index == 0 => (initial, 0),
+            None => return Err(JournalError::EntryRemoved { first_index }),
+        };
+        // the snapshot might already cover every entry
+        if index < len {
+            for entry in iter_from(index).map_err(journal_error)? {
+                apply(&mut state, entry.map_err(journal_error)?);
+            }
+        }
+        Ok(state)
+    }
+
+    /// Load the newest valid snapshot that was saved with an index of at most `max_index`.
+    fn load_up_to(&self, max_index: usize) -> Result<Option<(T, usize)>, JournalError<D::Error>> {
+        self.load_between(0, max_index)
+    }
+
+    /// Like [`load_up_to`](Snapshots::load_up_to), but the index has to be at least `min_index` as well.
+    fn load_between(&self, min_index: usize, max_index: usize) -> Result<Option<(T, usize)>, JournalError<D::Error>> {
+        let indices = self.indices().map_err(JournalError::IOError)?;
+        for index in indices.into_iter().filter(|&index| (min_index..=max_index).contains(&index)) {
+            match self.load(index) {
+                Ok(state) => return Ok(Some((state, index))),
+                // fall back to the next older snapshot
+                Err(err) if is_corrupted(&err) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    fn load(&self, index: usize) -> Result<T, JournalError<D::Error>> {
+        let file = File::open(snapshot_path(&self.directory, index))
+            .map_err(JournalError::IOError)?;
+        JournalReader::<T, D>::with_deserializer(file, self.deserializer.clone())?
+            .iter()
+            .next()
+            .unwrap_or_else(|| Err(JournalError::IOError(std::io::Error::new(ErrorKind::InvalidData, "Snapshot is empty"))))
+    }
+
+    /// The indices of all saved snapshots, newest first.
+    fn indices(&self) -> std::io::Result<Vec<usize>> {
+        let mut indices = Vec::new();
+        for dir_entry in fs::read_dir(&self.directory)? {
+            if let Some(index) = snapshot_index(&dir_entry?.path()) {
+                indices.push(index);
+            }
+        }
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(indices)
+    }
+}
+
+/// Turn an error of the journal into one of the snapshots.
+fn journal_error<E, JE>(err: JournalError<JE>) -> JournalError<E>
+where JE: std::error::Error + Send + Sync + 'static {
+    match err {
+        JournalError::SerializationError(err) => JournalError::IOError(std::io::Error::new(ErrorKind::InvalidData, err)),
+        JournalError::IndexOutOfBounds => JournalError::IndexOutOfBounds,
+        JournalError::IOError(err) => JournalError::IOError(err),
+        JournalError::Corrupted { offset } => JournalError::Corrupted { offset },
+        JournalError::InvalidHeader(err) => JournalError::InvalidHeader(err),
+        JournalError::EntryRemoved { first_index } => JournalError::EntryRemoved { first_index },
+        JournalError::SyncFailed { indices, error } => JournalError::SyncFailed { indices, error },
+        JournalError::NotSynced(err) => JournalError::NotSynced(err),
+    }
+}
+
+/// Whether `err` means the snapshot can't be used, rather than that it couldn't be read.
+fn is_corrupted<E>(err: &JournalError<E>) -> bool {
+    match err {
+        JournalError::IOError(err) => matches!(err.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof),
+        JournalError::IndexOutOfBounds | JournalError::EntryRemoved { .. } | JournalError::SyncFailed { .. }
+            | JournalError::NotSynced(_) => false,
+        JournalError::SerializationError(_) | JournalError::Corrupted { .. } | JournalError::InvalidHeader(_) => true,
+    }
+}
+
+fn snapshot_path(directory: &Path, index: usize) -> PathBuf {
+    directory.join(format!("{:020}.{}", index, SNAPSHOT_EXTENSION))
+}
+
+/// The index a snapshot was saved with if `path` is a snapshot file.
+fn snapshot_index(path: &Path) -> Option<usize> {
+    if path.extension()? != SNAPSHOT_EXTENSION {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    if stem.len() != 20 || !stem.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    stem.parse().ok()
+}
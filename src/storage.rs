@@ -0,0 +1,81 @@
+use std::io::{Read, Write, Seek, Cursor, Result, Error, ErrorKind};
+use std::fs::File;
+
+/// A backend journals can be stored in.
+///
+/// This is implemented for [`File`](std::fs::File), in-memory
+/// [`Cursor`](std::io::Cursor)s and boxed backends. Any other
+/// `Read + Write + Seek` type only needs an empty impl to store journals
+/// somewhere else, e.g. in an encrypted file:
+///
+/// ```ignore
+/// impl Storage for EncryptedFile {}
+/// ```
+///
+/// Truncating and syncing are optional. Without them, recovering a journal
+/// with a torn tail fails and syncing only flushes the backend.
+pub trait Storage: Read + Write + Seek {
+    /// Shrink the storage to `length` bytes.
+    ///
+    /// Fails with [`ErrorKind::Unsupported`](std::io::ErrorKind::Unsupported) by default.
+    fn truncate(&mut self, _length: u64) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "Journal storage can't be truncated"))
+    }
+
+    /// Wait until all written data reached stable storage.
+    ///
+    /// Does nothing by default, the backend is always flushed before.
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Storage for File {
+    fn truncate(&mut self, length: u64) -> Result<()> {
+        self.set_len(length)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.sync_data()
+    }
+}
+
+impl Storage for Cursor<Vec<u8>> {
+    fn truncate(&mut self, length: u64) -> Result<()> {
+        self.get_mut().truncate(length as usize);
+        Ok(())
+    }
+}
+
+impl Storage for Cursor<&mut Vec<u8>> {
+    fn truncate(&mut self, length: u64) -> Result<()> {
+        self.get_mut().truncate(length as usize);
+        Ok(())
+    }
+}
+
+impl<S> Storage for Box<S>
+where S: Storage + ?Sized {
+    fn truncate(&mut self, length: u64) -> Result<()> {
+        self.as_mut().truncate(length)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.as_mut().sync()
+    }
+}
+
+/// Make the creation, rename or removal of the file at `path` durable.
+#[cfg(unix)]
+pub(crate) fn sync_directory(path: &std::path::Path) -> Result<()> {
+    match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => File::open(directory)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories can't be opened for syncing on this platform.
+#[cfg(not(unix))]
+pub(crate) fn sync_directory(_path: &std::path::Path) -> Result<()> {
+    Ok(())
+}
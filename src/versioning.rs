@@ -0,0 +1,286 @@
+use std::io::{Read, Write, ErrorKind};
+use std::fmt::{self, Debug, Display};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use crate::*;
+use crate::header::{FileHeader, Layer};
+
+/// Entries are written to the migrated journal in batches of this size.
+const MIGRATION_BUFFER: usize = 64 * 1024;
+
+/// Converts an entry that was written with an older schema `version` into
+/// the current type `T`. It gets the wrapped deserializer to decode the old
+/// type with the same format.
+///
+/// Upcasters aren't chained, every one of them converts straight to the
+/// current type. When `T` changes again, the existing upcasters have to be
+/// updated to produce the new type as well.
+///
+/// The wrapped deserializer doesn't know the position of the entry, so put the
+/// [`VersionedSerializer`](VersionedSerializer) inside of an
+/// [`Encrypted`](crate::encryption::Encrypted) one and not the other way around.
+pub type Upcaster<T, D> = (u32, Upcast<T, D>);
+
+/// The conversion of an [`Upcaster`](Upcaster).
+pub type Upcast<T, D> = fn(&D, &mut dyn Read) -> Result<Option<T>, <D as JournalDeserialize<T>>::Error>;
+
+/// Wraps a serializer and writes the schema version in front of every entry.
+///
+/// Increase the version whenever `T` changes in a way the format can't read
+/// anymore, and register an [`Upcaster`](Upcaster) for the old version with
+/// [`VersionedDeserializer::with_upcaster`](VersionedDeserializer::with_upcaster).
+/// Old entries are converted while they are read, so the journal never has
+/// to be rewritten.
+///
+/// The header records whether entries carry a version. Entries of a journal
+/// that was written without a `VersionedSerializer` are read as version 0,
+/// see [`with_unversioned`](VersionedDeserializer::with_unversioned). Such a
+/// journal can't be appended to with a `VersionedSerializer` though, it has
+/// to be rewritten with [`migrate_to_versioned`](migrate_to_versioned) first.
+///
+/// ```ignore
+/// let journal = IndexedJournal::with_serializer(file,
+///     VersionedSerializer::new(BincodeSerializer, 2),
+///     VersionedDeserializer::new(BincodeDeserializer, 2).with_upcaster(1, upcast_v1))?;
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct VersionedSerializer<S> {
+    inner: S,
+    version: u32,
+}
+
+impl<S> VersionedSerializer<S> {
+    /// Write every entry as schema `version`.
+    pub fn new(inner: S, version: u32) -> Self {
+        Self { inner, version }
+    }
+}
+
+/// Wraps a deserializer and upcasts entries that were written with an older schema version.
+pub struct VersionedDeserializer<T: 'static, D: JournalDeserialize<T>> {
+    inner: D,
+    version: u32,
+    upcasters: Vec<Upcaster<T, D>>,
+    /// The version of entries that were written without one
+    unversioned: u32,
+    /// Whether the entries start with their version
+    prefixed: bool,
+}
+
+impl<T, D: JournalDeserialize<T>> VersionedDeserializer<T, D> {
+    /// Read entries of schema `version` with `inner`, there are no upcasters yet.
+    pub fn new(inner: D, version: u32) -> Self {
+        Self { inner, version, upcasters: Vec::new(), unversioned: 0, prefixed: true }
+    }
+
+    /// Register an upcaster that converts entries of the older schema `version`.
+    ///
+    /// It replaces an upcaster that was registered for the same version before.
+    pub fn with_upcaster(mut self, version: u32, upcast: Upcast<T, D>) -> Self {
+        self.upcasters.retain(|(registered, _)| *registered != version);
+        self.upcasters.push((version, upcast));
+        self
+    }
+
+    /// Register all of these upcasters, see [`with_upcaster`](VersionedDeserializer::with_upcaster).
+    pub fn with_upcasters(self, upcasters: &[Upcaster<T, D>]) -> Self {
+        upcasters.iter().fold(self, |deserializer, &(version, upcast)| deserializer.with_upcaster(version, upcast))
+    }
+
+    /// Read the entries of journals that were written without a
+    /// [`VersionedSerializer`](VersionedSerializer) as schema `version`, 0 by default.
+    pub fn with_unversioned(mut self, version: u32) -> Self {
+        self.unversioned = version;
+        self
+    }
+}
+
+// derived impls would require `T: Clone` and `T: Debug`
+impl<T, D: JournalDeserialize<T>> Clone for VersionedDeserializer<T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            version: self.version,
+            upcasters: self.upcasters.clone(),
+            unversioned: self.unversioned,
+            prefixed: self.prefixed,
+        }
+    }
+}
+
+impl<T, D: JournalDeserialize<T> + Debug> Debug for VersionedDeserializer<T, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionedDeserializer")
+            .field("inner", &self.inner)
+            .field("version", &self.version)
+            .field("upcasters", &self.upcasters.iter().map(|(version, _)| version).collect::<Vec<_>>())
+            .field("unversioned", &self.unversioned)
+            .field("prefixed", &self.prefixed)
+            .finish()
+    }
+}
+
+/// The error of a [`VersionedSerializer`](VersionedSerializer) or [`VersionedDeserializer`](VersionedDeserializer).
+#[derive(Debug)]
+pub enum VersionedError<E> {
+    /// The wrapped serializer, deserializer or an upcaster failed.
+    Serialization(E),
+    /// The entry was written with a version that is neither current nor has an upcaster.
+    UnknownVersion(u32),
+    /// The version couldn't be read or written.
+    IOError(std::io::Error),
+}
+
+impl<E: Display> Display for VersionedError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionedError::Serialization(err) => write!(f, "{}", err),
+            VersionedError::UnknownVersion(version) => write!(f, "no upcaster for schema version {}", version),
+            VersionedError::IOError(err) => write!(f, "schema version can't be read or written: {}", err),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for VersionedError<E> {}
+
+impl<T, S> JournalSerialize<T> for VersionedSerializer<S>
+where S: JournalSerialize<T> {
+    type Error = VersionedError<S::Error>;
+    const FORMAT_ID: &'static str = S::FORMAT_ID;
+    const CODEC_ID: Option<&'static str> = S::CODEC_ID;
+    const CIPHER_ID: Option<&'static str> = S::CIPHER_ID;
+    const VERSIONED: bool = true;
+    const LAYERS: Layers = S::LAYERS.wrap(Layer::Versioned);
+
+    fn serialize(&self, value: T, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        self.serialize_at(value, EntryPosition::default(), writer)
+    }
+
+    fn serialize_at(&self, value: T, position: EntryPosition, writer: &mut dyn Write) -> Result<(), Self::Error> {
+        writer.write_all(&self.version.to_le_bytes())
+            .map_err(VersionedError::IOError)?;
+        self.inner.serialize_at(value, position, writer)
+            .map_err(VersionedError::Serialization)
+    }
+}
+
+impl<T, D> JournalDeserialize<T> for VersionedDeserializer<T, D>
+where D: JournalDeserialize<T> {
+    type Error = VersionedError<D::Error>;
+    const FORMAT_ID: &'static str = D::FORMAT_ID;
+    const CODEC_ID: Option<&'static str> = D::CODEC_ID;
+    const CIPHER_ID: Option<&'static str> = D::CIPHER_ID;
+    const VERSIONED: bool = true;
+    const LAYERS: Layers = D::LAYERS.wrap(Layer::Versioned);
+    const DEPENDS_ON_POSITION: bool = D::DEPENDS_ON_POSITION;
+
+    fn deserialize(&self, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        self.deserialize_at(EntryPosition::default(), reader)
+    }
+
+    fn deserialize_at(&self, position: EntryPosition, reader: &mut dyn Read) -> Result<Option<T>, Self::Error> {
+        let version = if self.prefixed {
+            let mut version = [0; 4];
+            match reader.read_exact(&mut version) {
+                Ok(()) => u32::from_le_bytes(version),
+                // the entry is only partially written
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(VersionedError::IOError(err)),
+            }
+        } else {
+            self.unversioned
+        };
+        if version == self.version {
+            return self.inner.deserialize_at(position, reader)
+                .map_err(VersionedError::Serialization);
+        }
+        match self.upcasters.iter().find(|(from, _)| *from == version) {
+            Some((_, upcast)) => upcast(&self.inner, reader)
+                .map_err(VersionedError::Serialization),
+            None => Err(VersionedError::UnknownVersion(version)),
+        }
+    }
+
+    fn relocate(&self, mut entry: Vec<u8>, from: EntryPosition, to: EntryPosition) -> Result<Vec<u8>, Self::Error> {
+        if !self.prefixed {
+            return self.inner.relocate(entry, from, to)
+                .map_err(VersionedError::Serialization);
+        }
+        if entry.len() < 4 {
+            return Err(VersionedError::IOError(std::io::Error::new(ErrorKind::UnexpectedEof, "Journal entry has no schema version")));
+        }
+        let inner = self.inner.relocate(entry.split_off(4), from, to)
+            .map_err(VersionedError::Serialization)?;
+        entry.extend_from_slice(&inner);
+        Ok(entry)
+    }
+
+    fn without_versions(self) -> Option<Self> {
+        Some(Self { prefixed: false, ..self })
+    }
+}
+
+/// Rewrite the journal at `path`, which was written without a
+/// [`VersionedSerializer`](VersionedSerializer), so that every entry carries
+/// its schema version.
+///
+/// The entries are read with `deserializer`, which upcasts them like all
+/// entries without a version, see [`with_unversioned`](VersionedDeserializer::with_unversioned),
+/// and written again with the versioned `serializer`. Until a journal was
+/// migrated, an [`IndexedJournal`](crate::indexed_journal::IndexedJournal)
+/// with a versioned deserializer can read it, but refuses to write to it:
+/// new entries would carry no version either and be upcast like old ones.
+/// Afterwards, writers without a `VersionedSerializer` are rejected instead.
+///
+/// Like with [`compact`](crate::compact), the journal is replaced only once
+/// the migrated copy was synced, and it must not be open in the meantime. The
+/// copy keeps the checksum, but not the type fingerprint of the old entries.
+/// Journals that are empty or versioned already are left alone.
+///
+/// Returns the number of migrated entries.
+pub fn migrate_to_versioned<T, S, D, P>(path: P, serializer: S, deserializer: D) -> Result<usize, JournalError<D::Error>>
+where S: JournalSerialize<T, Error = D::Error> + Debug,
+      D: JournalDeserialize<T> + Debug,
+      T: Debug,
+      P: AsRef<Path> {
+    let path = path.as_ref();
+    if !S::VERSIONED {
+        return Err(JournalError::InvalidHeader(HeaderError::VersioningMismatch { expected: false }));
+    }
+    let mut file = File::open(path)
+        .map_err(JournalError::IOError)?;
+    let header = match FileHeader::read(&mut file)? {
+        Some(header) if !header.versioned => header,
+        _ => return Ok(0),
+    };
+
+    let mut reader = JournalReader::with_deserializer(&mut file, deserializer)?;
+    let temp_path = migration_path(path);
+    let temp_file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&temp_path)
+        .map_err(JournalError::IOError)?;
+    let mut writer = JournalWriter::with_serializer(temp_file, serializer)?
+        .with_checksum(header.checksum)
+        .with_buffer_capacity(MIGRATION_BUFFER);
+    let mut migrated = 0;
+    for entry in reader.iter() {
+        writer.store_entry(entry?)?;
+        migrated += 1;
+    }
+    writer.sync()?;
+    drop(writer);
+    drop(reader);
+
+    std::fs::rename(&temp_path, path)
+        .map_err(JournalError::IOError)?;
+    storage::sync_directory(path)
+        .map_err(JournalError::IOError)?;
+    Ok(migrated)
+}
+
+/// The temporary file a migrated journal is written to, e.g. `journal.migrate` for `journal`.
+fn migration_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".migrate");
+    path.with_file_name(file_name)
+}